@@ -0,0 +1,53 @@
+local Color = engine.Color
+local Point = engine.math.Point
+local Vec = engine.math.Vec
+local Lambertian = engine.materials.Lambertian
+local transforms = engine.transforms
+
+local ground = Lambertian:from_albedo(Color:new(0.48, 0.83, 0.53))
+local fallback = Lambertian:from_albedo(Color:new(0.7, 0.7, 0.7))
+
+-- the geometry is loaded once. Every instance only stores its own transform.
+local sphere = engine.shapes.Mesh:from_obj("examples/models/icosphere.obj", fallback)
+local crate = engine.shapes.Box:new(Point:new(-0.5, 0, -0.5), Point:new(0.5, 1, 0.5), Lambertian:from_albedo(Color:new(0.6, 0.4, 0.2)))
+
+local instances = engine.ObjectList:new()
+
+local count = 32
+for i = 0, count - 1 do
+    for j = 0, count - 1 do
+        local x = 1.5 * (i - count / 2)
+        local z = -1.5 * j
+        local size = 0.3 + 0.2 * math.random()
+        local placement = transforms.Scale:new(size, size, size)
+            :and_then(transforms.RotateY:new(360 * math.random()))
+            :and_then(transforms.Translate:new(x, 0, z))
+
+        if (i + j) % 2 == 0 then
+            instances:add(engine.Instance:new(sphere, placement:and_then(transforms.Translate:new(0, size, 0))))
+        else
+            instances:add(engine.Instance:new(crate, placement))
+        end
+    end
+end
+
+local objects = engine.ObjectList:new()
+
+-- the top-level BVH is built over the instances
+objects:add(engine.BVH:new(instances))
+objects:add(engine.shapes.Plane:new(Point:new(0, 0, 0), Vec:new(0, 1, 0), ground))
+
+local cam = engine.Camera:new(400, 16 / 9)
+
+cam.samples_per_pixel = 100
+cam.max_depth = 50
+
+cam.field_of_view = 40
+cam.look_from = Point:new(0, 6, 8)
+cam.look_at = Point:new(0, 0, -12)
+cam.vup = Vec:new(0, 1, 0)
+cam.background = engine.Background:from_color(Color:new(0.7, 0.8, 1.0))
+
+cam.defocus_angle = 0
+
+return engine.Scene:new(cam, objects)
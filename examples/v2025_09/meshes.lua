@@ -0,0 +1,35 @@
+local Color = engine.Color
+local Point = engine.math.Point
+local Vec = engine.math.Vec
+local Lambertian = engine.materials.Lambertian
+local Mesh = engine.shapes.Mesh
+
+local ground = Lambertian:from_texture(engine.textures.Checker:from_colors(0.5, Color:new(0.2, 0.3, 0.1), Color:new(0.9, 0.9, 0.9)))
+local fallback = Lambertian:from_albedo(Color:new(0.7, 0.7, 0.7))
+
+local objects = engine.ObjectList:new()
+
+objects:add(engine.shapes.Plane:new(Point:new(0, -1, 0), Vec:new(0, 1, 0), ground))
+
+-- the materials come from the model's .mtl file. The last argument is for the faces without one.
+local smooth_sphere = Mesh:from_obj("examples/models/icosphere.obj", fallback)
+objects:add(smooth_sphere:translate(-1.1, 0, 0))
+
+local light = engine.materials.DiffuseLight:from_emission(Color:new(8, 8, 8))
+objects:add(engine.shapes.Sphere:new(Point:new(1.5, 1.5, 1.0), 0.5, light))
+objects:add(engine.shapes.Sphere:new(Point:new(1.1, 0, 0), 1, Lambertian:from_albedo(Color:new(0.1, 0.2, 0.5))))
+
+local cam = engine.Camera:new(400, 16 / 9)
+
+cam.samples_per_pixel = 100
+cam.max_depth = 50
+
+cam.field_of_view = 40
+cam.look_from = Point:new(0, 1.5, 7)
+cam.look_at = Point:new(0, 0, 0)
+cam.vup = Vec:new(0, 1, 0)
+cam.background = engine.Background:from_color(Color:new(0.5, 0.6, 0.8))
+
+cam.defocus_angle = 0
+
+return engine.Scene:new(cam, objects)
@@ -143,6 +143,9 @@ local function setup_camera()
   camera.defocus_angle = 0.6
   camera.focus_distance = 10
 
+  -- keep the highlights around the sun from blowing out to flat white
+  camera.tone_mapping = "aces"
+
   return camera
 end
 
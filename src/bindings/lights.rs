@@ -0,0 +1,82 @@
+use crate::bindings::lua;
+use crate::bindings::macros::from_user_data;
+use crate::core::lights::Light;
+use crate::core::math::{Point, Real, Vec3D};
+use crate::core::Color;
+use mlua::{AnyUserData, Lua, Table};
+
+pub(crate) fn new_table(lua: &Lua) -> mlua::Result<Table> {
+    let lights = lua.create_table()?;
+    lights.set("Point", new_point_table(lua)?)?;
+    lights.set("Spot", new_spot_table(lua)?)?;
+    lights.set("Directional", new_directional_table(lua)?)?;
+    Ok(lights)
+}
+
+fn new_point_table(lua: &Lua) -> mlua::Result<Table> {
+    lua::new_table(
+        lua,
+        lua.create_function(
+            |_, (_, position, color, intensity): (Table, AnyUserData, AnyUserData, Real)| {
+                let position = from_user_data!(position, Point);
+                let color = from_user_data!(color, Color);
+                Ok(Light::point(position, color, intensity))
+            },
+        ),
+    )
+}
+
+fn new_spot_table(lua: &Lua) -> mlua::Result<Table> {
+    lua::new_table(
+        lua,
+        lua.create_function(
+            |_,
+             (_, position, target, color, intensity, inner_angle, outer_angle): (
+                Table,
+                AnyUserData,
+                AnyUserData,
+                AnyUserData,
+                Real,
+                Real,
+                Real,
+            )| {
+                let position = from_user_data!(position, Point);
+                let target = from_user_data!(target, Point);
+                let color = from_user_data!(color, Color);
+                Ok(Light::spot(
+                    position,
+                    target,
+                    color,
+                    intensity,
+                    inner_angle,
+                    outer_angle,
+                ))
+            },
+        ),
+    )
+}
+
+fn new_directional_table(lua: &Lua) -> mlua::Result<Table> {
+    lua::new_table(
+        lua,
+        lua.create_function(
+            |_,
+             (_, direction, color, intensity, angular_diameter): (
+                Table,
+                AnyUserData,
+                AnyUserData,
+                Real,
+                Option<Real>,
+            )| {
+                let direction = from_user_data!(direction, Vec3D);
+                let color = from_user_data!(color, Color);
+                Ok(Light::directional(
+                    direction,
+                    color,
+                    intensity,
+                    angular_diameter.unwrap_or(0.0),
+                ))
+            },
+        ),
+    )
+}
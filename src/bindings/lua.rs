@@ -1,13 +1,18 @@
 use crate::bindings;
 use crate::bindings::macros::from_user_data;
 use crate::bindings::schemas::{CameraSchema, SceneSchema};
-use crate::bindings::{materials, shapes, textures, transforms};
+use crate::bindings::{lights, materials, shapes, textures, transforms};
 use crate::core::camera::Background;
 use crate::core::color::ColorKind;
-use crate::core::math::Real;
+use crate::core::lights::Light;
+use crate::core::math::{Matrix, Real, Vec3D};
+use crate::core::sky::Sky;
+use crate::core::transform::Transform;
 use crate::core::Hittable::BVH;
-use crate::core::{bvh, Color, HittableList};
-use mlua::{AnyUserData, Function, Lua, Result, Table};
+use crate::core::{bvh, Color, Hittable, HittableList};
+use mlua::{AnyUserData, Function, Lua, LuaSerdeExt, Result, Table, UserData, Value};
+use std::path::Path;
+use std::sync::Arc;
 
 pub(crate) fn new_table(lua: &Lua, function: Result<Function>) -> Result<Table> {
     let table = lua.create_table()?;
@@ -43,6 +48,54 @@ fn new_background_table(lua: &Lua) -> Result<Table> {
             Ok(background)
         })?,
     )?;
+    table.set(
+        "from_environment",
+        lua.create_function(|_, (_, path, options): (Table, String, Option<Table>)| {
+            let (rotation, intensity) = match options {
+                Some(options) => (
+                    options.get::<Option<Real>>("rotation")?,
+                    options.get::<Option<Real>>("intensity")?,
+                ),
+                None => (None, None),
+            };
+            Background::from_environment(
+                Path::new(&path),
+                rotation.unwrap_or(0.0),
+                intensity.unwrap_or(1.0),
+            )
+            .map_err(|err| {
+                mlua::Error::RuntimeError(format!("Unable to load environment map {path}: {err}"))
+            })
+        })?,
+    )?;
+    table.set(
+        "from_sky",
+        lua.create_function(|lua, (_, options): (Table, Option<Table>)| {
+            let options = match options {
+                Some(options) => options,
+                None => lua.create_table()?,
+            };
+            let sun_direction = match options.get::<Option<AnyUserData>>("sun_direction")? {
+                Some(sun_direction) => from_user_data!(sun_direction, Vec3D),
+                None => Vec3D::new(0.0, 1.0, 0.0),
+            };
+            let ground_albedo = match options.get::<Option<AnyUserData>>("ground_albedo")? {
+                Some(ground_albedo) => from_user_data!(ground_albedo, Color),
+                None => Color::from_scalar(0.2),
+            };
+            let sky = Sky::new(
+                sun_direction,
+                options.get::<Option<Real>>("turbidity")?.unwrap_or(3.0),
+                options.get::<Option<Real>>("intensity")?.unwrap_or(1.0),
+                options.get::<Option<Real>>("sun_intensity")?.unwrap_or(1.0),
+                options
+                    .get::<Option<Real>>("sun_angular_diameter")?
+                    .unwrap_or(0.53),
+                ground_albedo,
+            );
+            Ok(Background::from_sky(sky))
+        })?,
+    )?;
 
     Ok(table)
 }
@@ -57,23 +110,97 @@ fn new_object_list_table(lua: &Lua) -> Result<Table> {
 fn new_bvh_table(lua: &Lua) -> Result<Table> {
     new_table(
         lua,
-        lua.create_function(|_, (_, h_list): (Table, AnyUserData)| {
-            let hittable_list = from_user_data!(h_list, HittableList);
-            let bvh = bvh::BVH::from_list(hittable_list);
-            Ok(BVH(bvh))
-        }),
+        lua.create_function(
+            |lua, (_, h_list, options): (Table, AnyUserData, Option<Table>)| {
+                let hittable_list = from_user_data!(h_list, HittableList);
+                let strategy: Option<bvh::SplitStrategy> = match options {
+                    Some(options) => lua.from_value(options.get::<Value>("strategy")?)?,
+                    None => None,
+                };
+                let bvh = bvh::BVH::from_list(hittable_list, strategy.unwrap_or_default());
+                Ok(BVH(bvh))
+            },
+        ),
+    )
+}
+
+fn new_instance_table(lua: &Lua) -> Result<Table> {
+    new_table(
+        lua,
+        lua.create_function(
+            |_, (_, shape, transform): (Table, AnyUserData, AnyUserData)| {
+                let transform_matrix = from_user_data!(transform, Matrix);
+                Ok(Hittable::Transform(Transform::new(
+                    instance_geometry(&shape)?,
+                    transform_matrix,
+                )))
+            },
+        ),
     )
 }
 
+/// The bottom-level structure of a shape, built the first time the shape is instanced and kept
+/// with it, so the BVH of a list is built only once and shared by all of its instances.
+struct InstanceGeometry {
+    /// The list the geometry was built from, if it was a list. Since the list shares its
+    /// objects with the one in Lua until one of them is changed, this tells whether the
+    /// geometry is out of date.
+    list: Option<HittableList>,
+    geometry: Arc<Hittable>,
+}
+
+impl UserData for InstanceGeometry {}
+
+/// Accepts both a shape and an object list, which are left untouched.
+fn instance_geometry(shape: &AnyUserData) -> Result<Arc<Hittable>> {
+    const KEY: &str = "instance_geometry";
+
+    if let Some(cached) = shape.named_user_value::<Option<AnyUserData>>(KEY)? {
+        let cached = cached.borrow::<InstanceGeometry>()?;
+        let is_current = match &cached.list {
+            Some(list) => list.shares_objects(&*shape.borrow::<HittableList>()?),
+            None => true,
+        };
+        if is_current {
+            return Ok(Arc::clone(&cached.geometry));
+        }
+    }
+
+    let (list, source) = if shape.is::<HittableList>() {
+        let list = from_user_data!(shape, HittableList);
+        (Some(list.clone()), Hittable::List(list))
+    } else {
+        (None, from_user_data!(shape, Hittable))
+    };
+    let geometry = Arc::new(source.into_bottom_level());
+    shape.set_named_user_value(
+        KEY,
+        InstanceGeometry {
+            list,
+            geometry: Arc::clone(&geometry),
+        },
+    )?;
+    Ok(geometry)
+}
+
 fn new_scene_table(lua: &Lua) -> Result<Table> {
     new_table(
         lua,
-        lua.create_function(|_, (_, camera, objects): (Table, AnyUserData, AnyUserData)| {
-            let camera: CameraSchema = from_user_data!(camera, CameraSchema);
-            let objects = from_user_data!(objects, HittableList);
-            let scene: SceneSchema = SceneSchema::new(camera, objects);
-            Ok(scene)
-        }),
+        lua.create_function(
+            |_, (_, camera, objects, lights): (Table, AnyUserData, AnyUserData, Option<Table>)| {
+                let camera: CameraSchema = from_user_data!(camera, CameraSchema);
+                let objects = from_user_data!(objects, HittableList);
+                let mut scene_lights: Vec<Light> = vec![];
+                if let Some(lights) = lights {
+                    for light in lights.sequence_values::<AnyUserData>() {
+                        let light = light?;
+                        scene_lights.push(from_user_data!(light, Light));
+                    }
+                }
+                let scene: SceneSchema = SceneSchema::new(camera, objects, scene_lights);
+                Ok(scene)
+            },
+        ),
     )
 }
 
@@ -89,6 +216,27 @@ pub(crate) fn new_color_table(lua: &Lua) -> Result<Table> {
     Ok(table)
 }
 
+/// Exposes the `--set` variables of the command line as `engine.args`. Numbers and booleans
+/// are converted, everything else stays a string.
+pub(crate) fn set_args(lua: &Lua, variables: &[(String, String)]) -> Result<()> {
+    let args = lua.create_table()?;
+    for (key, value) in variables {
+        let value = if let Ok(integer) = value.parse::<i64>() {
+            Value::Integer(integer)
+        } else if let Ok(number) = value.parse::<f64>() {
+            Value::Number(number)
+        } else if let Ok(boolean) = value.parse::<bool>() {
+            Value::Boolean(boolean)
+        } else {
+            Value::String(lua.create_string(value)?)
+        };
+        args.set(key.as_str(), value)?;
+    }
+
+    let engine: Table = lua.globals().get("engine")?;
+    engine.set("args", args)
+}
+
 pub(crate) fn set_engine(lua: &Lua) -> Result<()> {
     let engine = lua.create_table()?;
 
@@ -98,11 +246,13 @@ pub(crate) fn set_engine(lua: &Lua) -> Result<()> {
     engine.set("textures", textures::new_table(lua)?)?;
     engine.set("shapes", shapes::new_table(lua)?)?;
     engine.set("transforms", transforms::new_table(lua)?)?;
+    engine.set("lights", lights::new_table(lua)?)?;
     engine.set("Camera", new_camera_table(lua)?)?;
     engine.set("Background", new_background_table(lua)?)?;
     engine.set("ObjectList", new_object_list_table(lua)?)?;
     engine.set("Scene", new_scene_table(lua)?)?;
     engine.set("BVH", new_bvh_table(lua)?)?;
+    engine.set("Instance", new_instance_table(lua)?)?;
 
     lua.globals().set("engine", engine)?;
 
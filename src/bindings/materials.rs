@@ -1,15 +1,19 @@
 use crate::bindings::lua;
 use crate::bindings::macros::from_user_data;
-use crate::core::materials::{refractive_index, Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::core::materials::{
+    refractive_index, Conductor, ConductorPreset, Dielectric, DiffuseLight, Lambertian,
+    MaterialKind, Metal,
+};
 use crate::core::math::Real;
 use crate::core::textures::Texture;
 use crate::core::{Color, Material};
-use mlua::{AnyUserData, Lua, Table};
+use mlua::{AnyUserData, Lua, LuaSerdeExt, Table, Value};
 
 pub(crate) fn new_table(lua: &Lua) -> mlua::Result<Table> {
     let materials = lua.create_table()?;
     materials.set("Lambertian", new_lambertian_table(lua)?)?;
     materials.set("Metal", new_metal_table(lua)?)?;
+    materials.set("Conductor", new_conductor_table(lua)?)?;
     materials.set("Dielectric", new_dielectric_table(lua)?)?;
     materials.set("DiffuseLight", new_diffuse_light_table(lua)?)?;
     Ok(materials)
@@ -21,7 +25,9 @@ fn new_lambertian_table(lua: &Lua) -> mlua::Result<Table> {
         "from_texture",
         lua.create_function(|lua, (_, texture): (Table, AnyUserData)| {
             let texture: Texture = from_user_data!(texture, Texture);
-            Ok(Material::Lambertian(Lambertian::from_texture(texture)))
+            Ok(Material::new(MaterialKind::Lambertian(
+                Lambertian::from_texture(texture),
+            )))
         })?,
     )?;
 
@@ -29,7 +35,9 @@ fn new_lambertian_table(lua: &Lua) -> mlua::Result<Table> {
         "from_albedo",
         lua.create_function(|lua, (_, albedo): (Table, AnyUserData)| {
             let albedo: Color = from_user_data!(albedo, Color);
-            Ok(Material::Lambertian(Lambertian::from_albedo(albedo)))
+            Ok(Material::new(MaterialKind::Lambertian(
+                Lambertian::from_albedo(albedo),
+            )))
         })?,
     )?;
 
@@ -41,17 +49,95 @@ fn new_metal_table(lua: &Lua) -> mlua::Result<Table> {
         lua,
         lua.create_function(|_, (_, albedo, fuzz): (Table, AnyUserData, Real)| {
             let albedo: Color = from_user_data!(albedo, Color);
-            Ok(Material::Metal(Metal::new(albedo, fuzz)))
+            Ok(Material::new(MaterialKind::Metal(Metal::new(albedo, fuzz))))
         }),
     )
 }
 
+fn new_conductor_table(lua: &Lua) -> mlua::Result<Table> {
+    let table = lua::new_table(
+        lua,
+        lua.create_function(
+            |_, (_, eta, k, options): (Table, AnyUserData, AnyUserData, Option<Table>)| {
+                let eta: Color = from_user_data!(eta, Color);
+                let k: Color = from_user_data!(k, Color);
+                let (roughness_u, roughness_v) = roughness(options)?;
+                Ok(Material::new(MaterialKind::Conductor(Conductor::new(
+                    eta,
+                    k,
+                    roughness_u,
+                    roughness_v,
+                ))))
+            },
+        ),
+    )?;
+
+    table.set(
+        "from_preset",
+        lua.create_function(|lua, (_, preset, options): (Table, Value, Option<Table>)| {
+            let preset: ConductorPreset = lua.from_value(preset)?;
+            let (roughness_u, roughness_v) = roughness(options)?;
+            Ok(Material::new(MaterialKind::Conductor(
+                Conductor::from_preset(preset, roughness_u, roughness_v),
+            )))
+        })?,
+    )?;
+    table.set(
+        "from_edge_tint",
+        lua.create_function(
+            |_,
+             (_, reflectivity, edge_tint, options): (
+                Table,
+                AnyUserData,
+                AnyUserData,
+                Option<Table>,
+            )| {
+                let reflectivity: Color = from_user_data!(reflectivity, Color);
+                let edge_tint: Color = from_user_data!(edge_tint, Color);
+                let (roughness_u, roughness_v) = roughness(options)?;
+                Ok(Material::new(MaterialKind::Conductor(
+                    Conductor::from_edge_tint(reflectivity, edge_tint, roughness_u, roughness_v),
+                )))
+            },
+        )?,
+    )?;
+
+    Ok(table)
+}
+
+/// The roughnesses along the two tangents: `roughness` for both, unless `roughness_u` or
+/// `roughness_v` override them.
+fn roughness(options: Option<Table>) -> mlua::Result<(Real, Real)> {
+    let Some(options) = options else {
+        return Ok((0.0, 0.0));
+    };
+    let roughness = options.get::<Option<Real>>("roughness")?.unwrap_or(0.0);
+    Ok((
+        options.get::<Option<Real>>("roughness_u")?.unwrap_or(roughness),
+        options.get::<Option<Real>>("roughness_v")?.unwrap_or(roughness),
+    ))
+}
+
 fn new_dielectric_table(lua: &Lua) -> mlua::Result<Table> {
     let table = lua::new_table(
         lua,
-        lua.create_function(|_, (_, refraction_index): (Table, Real)| {
-            Ok(Material::Dielectric(Dielectric::new(refraction_index)))
-        }),
+        lua.create_function(
+            |_, (_, refraction_index, options): (Table, Real, Option<Table>)| {
+                let (roughness, absorption) = match options {
+                    Some(options) => (
+                        options.get::<Option<Real>>("roughness")?.unwrap_or(0.0),
+                        match options.get::<Option<AnyUserData>>("absorption")? {
+                            Some(absorption) => from_user_data!(absorption, Color),
+                            None => Color::white(),
+                        },
+                    ),
+                    None => (0.0, Color::white()),
+                };
+                Ok(Material::new(MaterialKind::Dielectric(
+                    Dielectric::rough_absorbing(refraction_index, roughness, absorption),
+                )))
+            },
+        ),
     )?;
 
     let refractive_index = lua.create_table()?;
@@ -60,6 +146,19 @@ fn new_dielectric_table(lua: &Lua) -> mlua::Result<Table> {
     refractive_index.set("AIR", refractive_index::AIR)?;
     refractive_index.set("WATER", refractive_index::WATER)?;
     refractive_index.set("DIAMOND", refractive_index::DIAMOND)?;
+    refractive_index.set("ICE", refractive_index::ICE)?;
+    refractive_index.set("ETHANOL", refractive_index::ETHANOL)?;
+    refractive_index.set("OLIVE_OIL", refractive_index::OLIVE_OIL)?;
+    refractive_index.set("ACRYLIC", refractive_index::ACRYLIC)?;
+    refractive_index.set("CROWN_GLASS", refractive_index::CROWN_GLASS)?;
+    refractive_index.set("QUARTZ", refractive_index::QUARTZ)?;
+    refractive_index.set("AMBER", refractive_index::AMBER)?;
+    refractive_index.set("EMERALD", refractive_index::EMERALD)?;
+    refractive_index.set("POLYCARBONATE", refractive_index::POLYCARBONATE)?;
+    refractive_index.set("FLINT_GLASS", refractive_index::FLINT_GLASS)?;
+    refractive_index.set("SAPPHIRE", refractive_index::SAPPHIRE)?;
+    refractive_index.set("CUBIC_ZIRCONIA", refractive_index::CUBIC_ZIRCONIA)?;
+    refractive_index.set("MOISSANITE", refractive_index::MOISSANITE)?;
 
     table.set("RefractiveIndex", refractive_index)?;
 
@@ -73,8 +172,8 @@ fn new_diffuse_light_table(lua: &Lua) -> mlua::Result<Table> {
         "from_emission",
         lua.create_function(|_, (_, emission_color): (Table, AnyUserData)| {
             let emission_color: Color = from_user_data!(emission_color, Color);
-            Ok(Material::DiffuseLight(DiffuseLight::from_emission(
-                emission_color,
+            Ok(Material::new(MaterialKind::DiffuseLight(
+                DiffuseLight::from_emission(emission_color),
             )))
         })?,
     )?;
@@ -82,7 +181,9 @@ fn new_diffuse_light_table(lua: &Lua) -> mlua::Result<Table> {
         "from_texture",
         lua.create_function(|_, (_, texture): (Table, AnyUserData)| {
             let texture: Texture = from_user_data!(texture, Texture);
-            Ok(Material::DiffuseLight(DiffuseLight::from_texture(texture)))
+            Ok(Material::new(MaterialKind::DiffuseLight(
+                DiffuseLight::from_texture(texture),
+            )))
         })?,
     )?;
     table.set(
@@ -91,8 +192,8 @@ fn new_diffuse_light_table(lua: &Lua) -> mlua::Result<Table> {
             |_, (_, texture, intensity): (Table, AnyUserData, AnyUserData)| {
                 let texture: Texture = from_user_data!(texture, Texture);
                 let intensity = from_user_data!(intensity, Color);
-                let diffuse_light = Material::DiffuseLight(DiffuseLight::from_texture_intensified(
-                    texture, intensity,
+                let diffuse_light = Material::new(MaterialKind::DiffuseLight(
+                    DiffuseLight::from_texture_intensified(texture, intensity),
                 ));
                 Ok(diffuse_light)
             },
@@ -104,9 +205,9 @@ fn new_diffuse_light_table(lua: &Lua) -> mlua::Result<Table> {
             |_, (_, emission_color, intensity): (Table, AnyUserData, AnyUserData)| {
                 let emission_color: Color = from_user_data!(emission_color, Color);
                 let intensity = from_user_data!(intensity, Color);
-                let diffuse_light = Material::DiffuseLight(
+                let diffuse_light = Material::new(MaterialKind::DiffuseLight(
                     DiffuseLight::from_emission_intensified(emission_color, intensity),
-                );
+                ));
                 Ok(diffuse_light)
             },
         )?,
@@ -1,3 +1,4 @@
+pub(crate) mod lights;
 pub(crate) mod lua;
 mod materials;
 mod math;
@@ -1,26 +1,40 @@
 use crate::bindings::macros::from_user_data;
+use crate::core::aov::Pass;
 use crate::core::camera::{Background, Image};
+use crate::core::filter::Filter;
+use crate::core::lights::{Light, Lights};
 use crate::core::math::{Point, Real, Vec3D, VecLike};
+use crate::core::projection::Projection;
+use crate::core::sampler::Sampler;
+use crate::core::shutter::ShutterCurve;
+use crate::core::tone_mapping::ToneMapping;
 use crate::core::{Camera, Color, Hittable, HittableList};
 use crate::settings;
 use crate::settings::Config;
-use mlua::{AnyUserData, UserData, UserDataFields};
+use mlua::{AnyUserData, LuaSerdeExt, UserData, UserDataFields, Value};
 use std::io;
 
 #[derive(Clone, Debug)]
 pub(crate) struct SceneSchema {
     camera: CameraSchema,
     objects: HittableList,
+    lights: Vec<Light>,
 }
 
 impl SceneSchema {
-    pub(crate) fn new(camera: CameraSchema, objects: HittableList) -> Self {
-        Self { camera, objects }
+    pub(crate) fn new(camera: CameraSchema, objects: HittableList, lights: Vec<Light>) -> Self {
+        Self {
+            camera,
+            objects,
+            lights,
+        }
     }
 
     pub(crate) fn render(&self, config: &'static Config) -> io::Result<()> {
         let camera = self.camera.build(config);
-        camera.render(&Hittable::List(self.objects.clone()), config)
+        let world = Hittable::List(self.objects.clone());
+        let lights = Lights::new(world.lights(), self.lights.clone());
+        camera.render(&world, &lights, config)
     }
 }
 
@@ -32,14 +46,31 @@ pub(crate) struct CameraSchema {
     image_width: u32,
     samples_per_pixel: Option<u32>,
     antialiasing: Option<bool>,
+    filter: Option<Filter>,
+    filter_radius: Option<Real>,
     max_depth: Option<u32>,
+    projection: Option<Projection>,
     field_of_view: Option<Real>,
+    orthographic_height: Option<Real>,
     look_from: Option<Point>,
     look_at: Option<Point>,
     defocus_angle: Option<Real>,
     focus_distance: Option<Real>,
+    shutter_open: Option<Real>,
+    shutter_close: Option<Real>,
+    shutter_curve: Option<ShutterCurve>,
+    rolling_shutter_duration: Option<Real>,
     background: Option<Background>,
     vup: Option<Vec3D>,
+    tone_mapping: Option<ToneMapping>,
+    exposure: Option<Real>,
+    white_point: Option<Real>,
+    seed: Option<u64>,
+    sampler: Option<Sampler>,
+    min_samples_per_pixel: Option<u32>,
+    adaptive_threshold: Option<Real>,
+    passes: Option<Vec<Pass>>,
+    denoise: Option<bool>,
 }
 
 impl CameraSchema {
@@ -49,14 +80,31 @@ impl CameraSchema {
             image_width,
             samples_per_pixel: None,
             antialiasing: None,
+            filter: None,
+            filter_radius: None,
             max_depth: None,
+            projection: None,
             field_of_view: None,
+            orthographic_height: None,
             look_from: None,
             look_at: None,
             defocus_angle: None,
             focus_distance: None,
+            shutter_open: None,
+            shutter_close: None,
+            shutter_curve: None,
+            rolling_shutter_duration: None,
             background: None,
             vup: None,
+            tone_mapping: None,
+            exposure: None,
+            white_point: None,
+            seed: None,
+            sampler: None,
+            min_samples_per_pixel: None,
+            adaptive_threshold: None,
+            passes: None,
+            denoise: None,
         }
     }
 
@@ -72,19 +120,42 @@ impl CameraSchema {
                 .unwrap_or(VecLike::<K>::new(default[0], default[1], default[2]))
         }
 
+        let overrides = config.app().scene().camera().overrides();
+
         Camera::builder(config)
-            .image(Image::new(self.image_width, self.aspect_ratio))
+            .image(Image::new(
+                overrides.image_width().unwrap_or(self.image_width),
+                self.aspect_ratio,
+            ))
             .antialiasing(self.antialiasing.unwrap_or(defaults.antialiasing()))
+            .filter(self.filter.unwrap_or(defaults.filter()))
+            .filter_radius(self.filter_radius)
             .samples_per_pixel(
-                self.samples_per_pixel
+                overrides
+                    .samples_per_pixel()
+                    .or(self.samples_per_pixel)
                     .unwrap_or(defaults.samples_per_pixel()),
             )
-            .max_depth(self.max_depth.unwrap_or(defaults.max_depth()))
+            .max_depth(
+                overrides
+                    .max_depth()
+                    .or(self.max_depth)
+                    .unwrap_or(defaults.max_depth()),
+            )
+            .projection(self.projection.unwrap_or(defaults.projection()))
             .field_of_view(self.field_of_view.unwrap_or(defaults.field_of_view()))
+            .orthographic_height(self.orthographic_height)
             .look_from(build_vec_like(&self.look_from, defaults.look_from()))
             .look_at(build_vec_like(&self.look_at, defaults.look_at()))
             .defocus_angle(self.defocus_angle.unwrap_or(defaults.defocus_angle()))
             .focus_distance(self.focus_distance.unwrap_or(defaults.focus_distance()))
+            .shutter_open(self.shutter_open.unwrap_or(defaults.shutter_open()))
+            .shutter_close(self.shutter_close.unwrap_or(defaults.shutter_close()))
+            .shutter_curve(self.shutter_curve.unwrap_or(defaults.shutter_curve()))
+            .rolling_shutter_duration(
+                self.rolling_shutter_duration
+                    .unwrap_or(defaults.rolling_shutter_duration()),
+            )
             .vup(build_vec_like(&self.vup, defaults.vup()))
             .background({
                 let default = defaults.background();
@@ -94,6 +165,21 @@ impl CameraSchema {
                         default[0], default[1], default[2],
                     )))
             })
+            .tone_mapping(self.tone_mapping.unwrap_or(defaults.tone_mapping()))
+            .exposure(self.exposure.unwrap_or(defaults.exposure()))
+            .white_point(self.white_point.unwrap_or(defaults.white_point()))
+            .seed(self.seed.unwrap_or(defaults.seed()))
+            .sampler(self.sampler.unwrap_or(defaults.sampler()))
+            .min_samples_per_pixel(
+                self.min_samples_per_pixel
+                    .unwrap_or(defaults.min_samples_per_pixel()),
+            )
+            .adaptive_threshold(
+                self.adaptive_threshold
+                    .unwrap_or(defaults.adaptive_threshold()),
+            )
+            .passes(self.passes.clone().unwrap_or(defaults.passes().to_vec()))
+            .denoise(self.denoise.unwrap_or(defaults.denoise()))
             .build()
     }
 }
@@ -112,12 +198,28 @@ impl UserData for CameraSchema {
         fields.add_field_method_set("antialiasing", |_, this, antialiasing| {
             Ok(this.antialiasing = Some(antialiasing))
         });
+        fields.add_field_method_set("filter", |lua, this, filter: Value| {
+            this.filter = Some(lua.from_value(filter)?);
+            Ok(())
+        });
+        fields.add_field_method_set("filter_radius", |_, this, filter_radius| {
+            this.filter_radius = Some(filter_radius);
+            Ok(())
+        });
         fields.add_field_method_set("max_depth", |_, this, max_depth| {
             Ok(this.max_depth = Some(max_depth))
         });
+        fields.add_field_method_set("projection", |lua, this, projection: Value| {
+            this.projection = Some(lua.from_value(projection)?);
+            Ok(())
+        });
         fields.add_field_method_set("field_of_view", |_, this, field_of_view| {
             Ok(this.field_of_view = Some(field_of_view))
         });
+        fields.add_field_method_set("orthographic_height", |_, this, orthographic_height| {
+            this.orthographic_height = Some(orthographic_height);
+            Ok(())
+        });
         fields.add_field_method_set("look_from", |_, this, look_from: AnyUserData| {
             let look_from = from_user_data!(look_from, Point);
             Ok(this.look_from = Some(look_from))
@@ -132,6 +234,25 @@ impl UserData for CameraSchema {
         fields.add_field_method_set("focus_distance", |_, this, focus_distance| {
             Ok(this.focus_distance = Some(focus_distance))
         });
+        fields.add_field_method_set("shutter_open", |_, this, shutter_open| {
+            this.shutter_open = Some(shutter_open);
+            Ok(())
+        });
+        fields.add_field_method_set("shutter_close", |_, this, shutter_close| {
+            this.shutter_close = Some(shutter_close);
+            Ok(())
+        });
+        fields.add_field_method_set("shutter_curve", |lua, this, shutter_curve: Value| {
+            this.shutter_curve = Some(lua.from_value(shutter_curve)?);
+            Ok(())
+        });
+        fields.add_field_method_set(
+            "rolling_shutter_duration",
+            |_, this, rolling_shutter_duration| {
+                this.rolling_shutter_duration = Some(rolling_shutter_duration);
+                Ok(())
+            },
+        );
         fields.add_field_method_set("background", |_, this, background: AnyUserData| {
             let background = from_user_data!(background, Background);
             Ok(this.background = Some(background))
@@ -140,5 +261,41 @@ impl UserData for CameraSchema {
             let vup = from_user_data!(vup, Vec3D);
             Ok(this.vup = Some(vup))
         });
+        fields.add_field_method_set("tone_mapping", |lua, this, tone_mapping: Value| {
+            this.tone_mapping = Some(lua.from_value(tone_mapping)?);
+            Ok(())
+        });
+        fields.add_field_method_set("exposure", |_, this, exposure| {
+            this.exposure = Some(exposure);
+            Ok(())
+        });
+        fields.add_field_method_set("white_point", |_, this, white_point| {
+            this.white_point = Some(white_point);
+            Ok(())
+        });
+        fields.add_field_method_set("seed", |_, this, seed| {
+            this.seed = Some(seed);
+            Ok(())
+        });
+        fields.add_field_method_set("sampler", |lua, this, sampler: Value| {
+            this.sampler = Some(lua.from_value(sampler)?);
+            Ok(())
+        });
+        fields.add_field_method_set("min_samples_per_pixel", |_, this, min_samples_per_pixel| {
+            this.min_samples_per_pixel = Some(min_samples_per_pixel);
+            Ok(())
+        });
+        fields.add_field_method_set("adaptive_threshold", |_, this, adaptive_threshold| {
+            this.adaptive_threshold = Some(adaptive_threshold);
+            Ok(())
+        });
+        fields.add_field_method_set("passes", |lua, this, passes: Value| {
+            this.passes = Some(lua.from_value(passes)?);
+            Ok(())
+        });
+        fields.add_field_method_set("denoise", |_, this, denoise| {
+            this.denoise = Some(denoise);
+            Ok(())
+        });
     }
 }
@@ -1,6 +1,7 @@
 use crate::bindings::lua;
 use crate::bindings::macros::from_user_data;
 use crate::core::math::{Point, Real, Vec3D};
+use crate::core::shapes::mesh::Mesh;
 use crate::core::shapes::planars::{Planar, Quad, Triangle};
 use crate::core::shapes::plane::Plane;
 use crate::core::shapes::quadrics::cone::{Cone, EndType};
@@ -11,6 +12,7 @@ use crate::core::shapes::{planars, Sphere};
 use crate::core::textures::Texture;
 use crate::core::{Color, Hittable, HittableList, Material};
 use mlua::{AnyUserData, Lua, Table};
+use std::path::Path;
 use std::sync::Arc;
 
 pub(crate) fn new_table(lua: &Lua) -> mlua::Result<Table> {
@@ -28,6 +30,7 @@ pub(crate) fn new_table(lua: &Lua) -> mlua::Result<Table> {
     shapes.set("Plane", new_plane_table(lua)?)?;
     shapes.set("Cylinder", new_cylinder_table(lua)?)?;
     shapes.set("Cone", new_cone_table(lua)?)?;
+    shapes.set("Mesh", new_mesh_table(lua)?)?;
 
     Ok(shapes)
 }
@@ -325,3 +328,20 @@ fn new_cone_table(lua: &Lua) -> mlua::Result<Table> {
 
     Ok(table)
 }
+
+fn new_mesh_table(lua: &Lua) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+
+    table.set(
+        "from_obj",
+        lua.create_function(|_, (_, path, material): (Table, String, AnyUserData)| {
+            let material = from_user_data!(material, Material);
+            let mesh = Mesh::from_obj(Path::new(&path), material).map_err(|err| {
+                mlua::Error::RuntimeError(format!("Unable to load mesh {path}: {err}"))
+            })?;
+            Ok(Hittable::Mesh(mesh))
+        })?,
+    )?;
+
+    Ok(table)
+}
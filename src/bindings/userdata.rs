@@ -1,5 +1,6 @@
 use crate::bindings::macros::from_user_data;
 use crate::core::camera::Background;
+use crate::core::lights::Light;
 use crate::core::math::vector::CanAdd;
 use crate::core::math::{Point, Real, Vec3D, VecLike};
 use crate::core::{Color, Material};
@@ -80,3 +81,5 @@ impl UserData for Background {}
 impl UserData for Texture {}
 
 impl UserData for Material {}
+
+impl UserData for Light {}
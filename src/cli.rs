@@ -0,0 +1,86 @@
+use clap::Parser;
+use log::LevelFilter;
+use std::path::PathBuf;
+
+/// Renders the scene described by a Lua script.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub(crate) struct Args {
+    /// The Lua script that describes the scene
+    pub(crate) script: PathBuf,
+
+    /// Where to write the image. Overrides `output_file` in the config.
+    /// The extension determines the format.
+    #[arg(short, long, value_name = "PATH")]
+    pub(crate) output: Option<PathBuf>,
+
+    /// The config file. The extension can be omitted.
+    #[arg(short, long, value_name = "PATH", default_value = "config")]
+    pub(crate) config: PathBuf,
+
+    /// Overrides the image width of the camera. The aspect ratio is kept.
+    #[arg(long, value_name = "PIXELS")]
+    pub(crate) width: Option<u32>,
+
+    /// Overrides the samples per pixel of the camera
+    #[arg(long, value_name = "COUNT")]
+    pub(crate) spp: Option<u32>,
+
+    /// Overrides the maximum number of bounces of the camera
+    #[arg(long, value_name = "BOUNCES")]
+    pub(crate) max_depth: Option<u32>,
+
+    /// Saves the accumulated samples to this file periodically and at the end of the render
+    #[arg(long, value_name = "PATH")]
+    pub(crate) checkpoint: Option<PathBuf>,
+
+    /// Continues the render saved in this checkpoint. New checkpoints replace it,
+    /// unless `--checkpoint` says otherwise.
+    #[arg(long, value_name = "CHECKPOINT")]
+    pub(crate) resume: Option<PathBuf>,
+
+    /// Stops starting new passes after this many seconds and writes what has been rendered
+    #[arg(long, value_name = "SECONDS")]
+    pub(crate) time_limit: Option<f64>,
+
+    /// Writes an image of the number of samples taken by each pixel. Floating-point formats
+    /// store the counts, the others scale them so the maximum is white.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) sample_counts: Option<PathBuf>,
+
+    /// The number of rendering threads. Defaults to the number of logical cores.
+    #[arg(short = 'j', long, value_name = "COUNT")]
+    pub(crate) threads: Option<usize>,
+
+    /// Only logs errors
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub(crate) quiet: bool,
+
+    /// Logs the progress of the render
+    #[arg(short, long)]
+    pub(crate) verbose: bool,
+
+    /// Passes a value to the script through the `engine.args` table. Can be repeated.
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    pub(crate) variables: Vec<(String, String)>,
+}
+
+impl Args {
+    /// The log level requested through the flags. Without one, `RUST_LOG` decides.
+    pub(crate) fn log_level(&self) -> Option<LevelFilter> {
+        if self.quiet {
+            Some(LevelFilter::Error)
+        } else if self.verbose {
+            Some(LevelFilter::Info)
+        } else {
+            None
+        }
+    }
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, found `{s}`")),
+    }
+}
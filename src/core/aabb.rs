@@ -1,7 +1,5 @@
 use crate::core::math::interval::Interval;
-use crate::core::math::{Axis, Point, Vec3D};
-use crate::core::Ray;
-use crate::diagnostics::metrics;
+use crate::core::math::{Axis, Point, Real, Vec3D};
 use std::ops::Add;
 
 /// Axis-aligned Bounding Box
@@ -59,55 +57,6 @@ impl AABB {
         )
     }
 
-    pub(crate) fn axis_interval(&self, axis: &Axis) -> &Interval {
-        match axis {
-            Axis::Y => &self.y,
-            Axis::X => &self.x,
-            Axis::Z => &self.z,
-        }
-    }
-
-    pub(crate) fn hit(&self, ray: &Ray, ray_t: &Interval) -> bool {
-        metrics::increment_aabb_hit_attempt_count();
-
-        let ray_orig = ray.origin();
-        let ray_dir = ray.direction();
-
-        let mut t_min = ray_t.min;
-        let mut t_max = ray_t.max;
-
-        for axis in 0..3 {
-            let axis = &Axis::from_usize_unsafe(axis);
-            let ax = self.axis_interval(axis);
-            let dir_inverse = 1.0 / ray_dir[axis];
-
-            let t0 = (ax.min - ray_orig[axis]) * dir_inverse;
-            let t1 = (ax.max - ray_orig[axis]) * dir_inverse;
-
-            if t0 < t1 {
-                if t0 > t_min {
-                    t_min = t0
-                }
-                if t1 < t_max {
-                    t_max = t1
-                }
-            } else {
-                if t1 > t_min {
-                    t_min = t1
-                }
-                if t0 < t_max {
-                    t_max = t0
-                }
-            }
-
-            if t_max <= t_min {
-                return false;
-            }
-        }
-
-        true
-    }
-
     pub(crate) fn longest_axis(&self) -> Axis {
         if self.x.size() > self.y.size() {
             if self.x.size() > self.z.size() {
@@ -122,6 +71,19 @@ impl AABB {
         }
     }
 
+    pub(crate) fn centroid(&self) -> Point {
+        Point::new(
+            (self.x.min + self.x.max) / 2.0,
+            (self.y.min + self.y.max) / 2.0,
+            (self.z.min + self.z.max) / 2.0,
+        )
+    }
+
+    pub(crate) fn surface_area(&self) -> Real {
+        let (dx, dy, dz) = (self.x.size(), self.y.size(), self.z.size());
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
     fn pad_to_minimums(&mut self) {
         let delta = 0.0001;
         if self.x.size() < delta {
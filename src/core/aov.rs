@@ -0,0 +1,134 @@
+use crate::core::hittables::Hittable;
+use crate::core::math::{self, Real};
+use crate::core::{Color, Material};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Arbitrary output variables: extra images written by the same render as the beauty image,
+/// for compositing. The geometric passes come from the first hit of the camera rays, and are
+/// black where nothing was hit.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Pass {
+    /// The distance of the hit point along the viewing direction of the camera (camera-space z)
+    Depth,
+
+    /// The shading normal in world space
+    Normal,
+
+    /// The color of the material, without any lighting
+    Albedo,
+
+    /// The texture coordinates, in the red and green channels
+    Uv,
+
+    /// A color for every shape, a triangle mesh counting as one. The instances of a shape
+    /// share its color.
+    ObjectId,
+
+    /// A color for every material. The shapes given the same material share its color.
+    MaterialId,
+
+    /// The light that reached the camera after a single bounce
+    Direct,
+
+    /// The light that reached the camera after two or more bounces
+    Indirect,
+
+    /// The light emitted by the surfaces seen directly, and the background
+    Emission,
+}
+
+impl Pass {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Depth => "depth",
+            Self::Normal => "normal",
+            Self::Albedo => "albedo",
+            Self::Uv => "uv",
+            Self::ObjectId => "object_id",
+            Self::MaterialId => "material_id",
+            Self::Direct => "direct",
+            Self::Indirect => "indirect",
+            Self::Emission => "emission",
+        }
+    }
+
+    /// Whether the pass holds light, which is exposed and tone mapped like the beauty image.
+    /// The other passes hold data, which is written as is.
+    pub(crate) fn is_lighting(&self) -> bool {
+        matches!(self, Self::Direct | Self::Indirect | Self::Emission)
+    }
+
+    /// Whether the pass needs the first hit of the camera rays
+    pub(crate) fn is_geometric(&self) -> bool {
+        !self.is_lighting()
+    }
+}
+
+/// Numbers the primitives and the materials of a scene, in the order they are found in it, so
+/// the IDs don't change between renders. The instances of a shape are the same primitive, and
+/// the copies of a material the same material.
+pub(crate) struct SceneIds {
+    /// The IDs of the primitive and of the material a hit record can point to, by the address
+    /// of the primitive's handle to the material. The material itself is shared, but every
+    /// primitive has its own handle, so the addresses tell the primitives apart.
+    ids: HashMap<usize, (u32, u32)>,
+}
+
+impl SceneIds {
+    pub(crate) fn new(world: &Hittable) -> Self {
+        let mut primitives = vec![];
+        world.collect_materials(&mut primitives);
+
+        let mut material_ids = HashMap::new();
+        let mut ids = HashMap::new();
+        let mut object_count = 0;
+        for materials in primitives {
+            // a primitive shows up once for every instance of it
+            let seen = materials
+                .first()
+                .is_some_and(|material| ids.contains_key(&Self::address(material)));
+            if seen {
+                continue;
+            }
+
+            for material in materials {
+                let material_count = material_ids.len() as u32;
+                let material_id = *material_ids
+                    .entry(material.address())
+                    .or_insert(material_count);
+                ids.insert(Self::address(material), (object_count, material_id));
+            }
+            object_count += 1;
+        }
+        Self { ids }
+    }
+
+    /// The ID of the primitive that has `material`, which must come from a hit record
+    pub(crate) fn object(&self, material: &Material) -> u32 {
+        self.get(material).0
+    }
+
+    /// The ID of `material`, which must come from a hit record
+    pub(crate) fn material(&self, material: &Material) -> u32 {
+        self.get(material).1
+    }
+
+    fn get(&self, material: &Material) -> (u32, u32) {
+        let ids = self.ids.get(&Self::address(material));
+        ids.copied().unwrap_or_default()
+    }
+
+    fn address(material: &Material) -> usize {
+        material as *const Material as usize
+    }
+}
+
+/// A random but stable color for `id`, bright enough to be told apart from the black of the
+/// pixels where nothing was hit.
+pub(crate) fn id_color(id: u32) -> Color {
+    let hash = math::mix_seed(id as u64, 0);
+    let channel = |shift: u32| 0.2 + 0.8 * ((hash >> shift) & 0xff) as Real / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
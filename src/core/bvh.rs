@@ -1,137 +1,526 @@
 use crate::core::aabb::AABB;
-use crate::core::hittables::{HitRecord, ObjectRef};
+use crate::core::hittables::HitRecord;
 use crate::core::math::interval::Interval;
-use crate::core::math::Axis;
+use crate::core::math::{self, Axis, Point, Real};
 use crate::core::{Hittable, HittableList, Ray};
 use crate::diagnostics::metrics;
-use std::cmp::Ordering;
+use serde::Deserialize;
+use std::ops::Range;
 use std::sync::Arc;
 
 #[derive(Clone, Debug)]
 pub(crate) struct BVH {
-    left: ObjectRef,
-    right: ObjectRef,
+    /// The objects, ordered so that the ones in the same leaf are next to each other
+    objects: Arc<[Hittable]>,
+    nodes: Arc<[BVHNode]>,
     bbox: AABB,
 }
 
 impl BVH {
-    pub(crate) const PRIMITIVE_COUNT_PER_LEAF: u32 = 1;
+    pub(crate) const MAX_PRIMITIVES_PER_LEAF: usize = 4;
 
-    pub(crate) fn from_list(mut list: HittableList) -> Self {
-        let mut objects = list
-            .objects_mut()
+    pub(crate) fn from_list(list: HittableList, strategy: SplitStrategy) -> Self {
+        let boxes = list
+            .objects()
             .iter()
             .map(|object| {
                 if !object.is_finite() {
                     log::warn!("Infinite bounding box found.")
                 }
 
-                Arc::new(object.clone())
+                object.bounding_box().clone()
             })
-            .collect::<Vec<ObjectRef>>();
-        let objects = &mut objects;
-        Self::from_objects(objects, 0, objects.len())
-    }
+            .collect::<Vec<AABB>>();
 
-    fn from_objects(objects: &mut Vec<ObjectRef>, start: usize, end: usize) -> Self {
-        metrics::increment_bvh_init_count();
+        let (nodes, order) = BVHBuilder::new(strategy, Self::MAX_PRIMITIVES_PER_LEAF).build(&boxes);
+        let objects = order
+            .into_iter()
+            .map(|i| list.objects()[i].clone())
+            .collect::<Vec<Hittable>>();
 
-        let mut bbox = AABB::empty();
-        for i in start..end {
-            bbox = AABB::from_boxes(&bbox, objects[i].bounding_box());
+        Self {
+            objects: objects.into(),
+            bbox: boxes.iter().fold(AABB::empty(), |bbox, object_box| {
+                AABB::from_boxes(&bbox, object_box)
+            }),
+            nodes: nodes.into(),
         }
+    }
 
-        let axis = bbox.longest_axis();
+    pub(crate) fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
+        hit_nodes(&self.nodes, ray, ray_t, |i, ray_t| {
+            self.objects[i].hit(ray, ray_t)
+        })
+    }
 
-        let comparator = if axis == Axis::X {
-            Self::box_x_compare
-        } else if axis == Axis::Y {
-            Self::box_y_compare
-        } else {
-            Self::box_z_compare
-        };
+    pub(crate) fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
 
-        let object_span = end - start;
-        let (left, right) = if object_span == 1 {
-            (objects[start].clone(), objects[start].clone())
-        } else if object_span == 2 {
-            (objects[start].clone(), objects[start + 1].clone())
-        } else {
-            objects[start..end].sort_by(comparator);
-            let mid = start + object_span / 2;
-            let left = BVH::from_objects(objects, start, mid);
-            let right = BVH::from_objects(objects, mid, end);
-            (
-                Arc::new(Hittable::BVH(left)),
-                Arc::new(Hittable::BVH(right)),
-            )
+    pub(crate) fn objects(&self) -> &[Hittable] {
+        &self.objects
+    }
+
+    pub(crate) fn nodes(&self) -> &[BVHNode] {
+        &self.nodes
+    }
+}
+
+/// How the primitives of a node are divided between its two children.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SplitStrategy {
+    /// Splits at the median of the centroids along the longest axis.
+    Median,
+
+    /// Picks the split with the lowest cost according to the Surface Area Heuristic, evaluated
+    /// at the boundaries of equally-sized bins. A node stays a leaf if that's cheaper.
+    #[default]
+    Sah,
+}
+
+/// A node of a hierarchy built by [`BVHBuilder`], packed into 32 bytes so two of them fit
+/// in a cache line. The nodes are stored in depth-first order: the first child of an interior
+/// node comes right after it, so only the offset of the second child is stored. The bounds are
+/// single-precision, rounded outwards so they still enclose everything below them.
+#[derive(Clone, Debug)]
+#[repr(C, align(32))]
+pub(crate) struct BVHNode {
+    min: [f32; 3],
+    max: [f32; 3],
+
+    /// Leaves: the index of the first primitive. Interior nodes: the index of the second child.
+    offset: u32,
+
+    /// Zero for interior nodes
+    primitive_count: u16,
+
+    /// The axis the children were split along
+    axis: u8,
+}
+
+const _: () = assert!(std::mem::size_of::<BVHNode>() == 32);
+
+impl BVHNode {
+    fn leaf(bbox: &AABB, start: usize, count: usize) -> Self {
+        Self::new(bbox, start as u32, count as u16, 0)
+    }
+
+    fn interior(bbox: &AABB, second_child: usize, axis: &Axis) -> Self {
+        let axis = match axis {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
         };
+        Self::new(bbox, second_child as u32, 0, axis)
+    }
 
+    fn new(bbox: &AABB, offset: u32, primitive_count: u16, axis: u8) -> Self {
+        let (x, y, z) = (bbox.x(), bbox.y(), bbox.z());
         Self {
-            left: left.clone(),
-            right: right.clone(),
-            bbox,
+            min: [x.min, y.min, z.min].map(|min| (min as f32).next_down()),
+            max: [x.max, y.max, z.max].map(|max| (max as f32).next_up()),
+            offset,
+            primitive_count,
+            axis,
         }
     }
 
-    pub(crate) fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
-        metrics::increment_bvh_hit_attempt_count();
+    pub(crate) fn is_leaf(&self) -> bool {
+        self.primitive_count > 0
+    }
 
-        if !self.bbox.hit(ray, ray_t) {
-            metrics::increment_bvh_miss_count();
+    /// The indices of the primitives of a leaf
+    pub(crate) fn primitives(&self) -> Range<usize> {
+        let start = self.offset as usize;
+        start..start + self.primitive_count as usize
+    }
+
+    pub(crate) fn second_child(&self) -> usize {
+        self.offset as usize
+    }
+
+    pub(crate) fn bounding_box(&self) -> AABB {
+        AABB::from_points(
+            Point::new(
+                self.min[0] as Real,
+                self.min[1] as Real,
+                self.min[2] as Real,
+            ),
+            Point::new(
+                self.max[0] as Real,
+                self.max[1] as Real,
+                self.max[2] as Real,
+            ),
+        )
+    }
+
+    /// The slab test, with the reciprocal of the ray direction computed once per traversal.
+    fn hit(&self, origin: &[Real; 3], inverse_direction: &[Real; 3], ray_t: &Interval) -> bool {
+        metrics::increment_aabb_hit_attempt_count();
+
+        let mut t_min = ray_t.min;
+        let mut t_max = ray_t.max;
+
+        for axis in 0..3 {
+            let t0 = (self.min[axis] as Real - origin[axis]) * inverse_direction[axis];
+            let t1 = (self.max[axis] as Real - origin[axis]) * inverse_direction[axis];
+            let (t0, t1) = if inverse_direction[axis] < 0.0 {
+                (t1, t0)
+            } else {
+                (t0, t1)
+            };
+
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
+            if t_max <= t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Builds bounding volume hierarchies over anything that has a bounding box. This is shared by
+/// [`BVH`], whose primitives are hittables, and the meshes, whose primitives are triangles.
+pub(crate) struct BVHBuilder {
+    strategy: SplitStrategy,
+    max_primitives_per_leaf: usize,
+}
+
+impl BVHBuilder {
+    const BIN_COUNT: usize = 12;
+
+    /// Below this depth, the nodes are split at the median. This bounds the depth of the tree
+    /// (and the traversal stack) no matter how the primitives are laid out.
+    const MAX_SAH_DEPTH: usize = 32;
+    pub(crate) const MAX_DEPTH: usize = 64;
+
+    /// The cost of visiting an interior node, relative to [`Self::INTERSECTION_COST`]
+    pub(crate) const TRAVERSAL_COST: Real = 1.0;
+    pub(crate) const INTERSECTION_COST: Real = 1.0;
+
+    pub(crate) fn new(strategy: SplitStrategy, max_primitives_per_leaf: usize) -> Self {
+        Self {
+            strategy,
+            max_primitives_per_leaf,
+        }
+    }
+
+    /// Returns the nodes, with the root at index 0, and the order the primitives should be
+    /// stored in, so that the primitives of each leaf are next to each other.
+    pub(crate) fn build(&self, boxes: &[AABB]) -> (Vec<BVHNode>, Vec<usize>) {
+        let centroids = boxes.iter().map(AABB::centroid).collect::<Vec<Point>>();
+        let mut order = (0..boxes.len()).collect::<Vec<usize>>();
+        let mut nodes = vec![];
+
+        if !boxes.is_empty() {
+            self.build_node(boxes, &centroids, &mut order, 0..boxes.len(), 0, &mut nodes);
+        }
+        (nodes, order)
+    }
+
+    fn build_node(
+        &self,
+        boxes: &[AABB],
+        centroids: &[Point],
+        order: &mut [usize],
+        Range { start, end }: Range<usize>,
+        depth: usize,
+        nodes: &mut Vec<BVHNode>,
+    ) -> usize {
+        metrics::increment_bvh_init_count();
+
+        let bbox = order[start..end]
+            .iter()
+            .fold(AABB::empty(), |bbox, &i| AABB::from_boxes(&bbox, &boxes[i]));
+
+        // reserve the slot, so the parent comes before its children
+        let index = nodes.len();
+        let count = end - start;
+        nodes.push(BVHNode::leaf(&bbox, start, count));
+
+        if count == 1 {
+            return index;
+        }
+
+        let centroid_bounds = order[start..end].iter().fold(AABB::empty(), |bounds, &i| {
+            AABB::from_boxes(
+                &bounds,
+                &AABB::from_points(centroids[i].clone(), centroids[i].clone()),
+            )
+        });
+        let axis = centroid_bounds.longest_axis();
+        let must_split = count > self.max_primitives_per_leaf;
+
+        let mid = match self.strategy {
+            _ if depth >= Self::MAX_SAH_DEPTH => None,
+            SplitStrategy::Median if must_split => None,
+            SplitStrategy::Median => return index,
+            SplitStrategy::Sah => {
+                match self.sah_split(&bbox, boxes, centroids, &order[start..end], &axis) {
+                    Some((split, cost))
+                        if must_split || cost < count as Real * Self::INTERSECTION_COST =>
+                    {
+                        Some(start + Self::partition(&mut order[start..end], split))
+                    }
+                    None if must_split => None,
+                    _ => return index,
+                }
+            }
+        };
+
+        let mid = mid.unwrap_or_else(|| {
+            // the median split. It always divides the primitives, even when their centroids
+            // are all in the same spot
+            let mid = start + count / 2;
+            order[start..end].select_nth_unstable_by(mid - start, |&a, &b| {
+                centroids[a][&axis].total_cmp(&centroids[b][&axis])
+            });
+            mid
+        });
+
+        self.build_node(boxes, centroids, order, start..mid, depth + 1, nodes);
+        let right = self.build_node(boxes, centroids, order, mid..end, depth + 1, nodes);
+        nodes[index] = BVHNode::interior(&bbox, right, &axis);
+        index
+    }
+
+    /// Finds the cheapest split along `axis` for the primitives in `order`. Returns a predicate
+    /// that tells whether a primitive goes to the left child, and the cost of the split.
+    /// Returns `None` if the primitives can't be separated by their centroids.
+    fn sah_split<'a>(
+        &self,
+        bbox: &AABB,
+        boxes: &[AABB],
+        centroids: &'a [Point],
+        order: &[usize],
+        axis: &'a Axis,
+    ) -> Option<(impl Fn(usize) -> bool + use<'a>, Real)> {
+        let (min, max) = order
+            .iter()
+            .fold((math::INFINITY, -math::INFINITY), |(min, max), &i| {
+                (min.min(centroids[i][axis]), max.max(centroids[i][axis]))
+            });
+        if max - min <= 0.0 {
             return None;
         }
 
-        let hit_right = |t_max| {
-            self.right
-                .hit(ray, &Interval::new(ray_t.min, t_max))
-                .and_then(|hit| {
-                    metrics::increment_right_node_hit_attempt_count();
-                    Some(hit)
-                })
+        let bin_of = move |i: usize| {
+            let offset = (centroids[i][axis] - min) / (max - min);
+            ((offset * Self::BIN_COUNT as Real) as usize).min(Self::BIN_COUNT - 1)
         };
 
-        if let Some(left_rec) = self.left.hit(ray, ray_t) {
-            metrics::increment_left_node_hit_attempt_count();
-            hit_right(left_rec.t()).or(Some(left_rec))
-        } else {
-            hit_right(ray_t.max)
+        let mut counts = [0usize; Self::BIN_COUNT];
+        let mut bin_boxes: [Option<AABB>; Self::BIN_COUNT] = Default::default();
+        for &i in order {
+            let bin = bin_of(i);
+            counts[bin] += 1;
+            bin_boxes[bin] = Some(match &bin_boxes[bin] {
+                Some(bin_box) => AABB::from_boxes(bin_box, &boxes[i]),
+                None => boxes[i].clone(),
+            });
         }
-    }
 
-    pub(crate) fn bounding_box(&self) -> &AABB {
-        &self.bbox
+        // the area and the number of primitives to the right of each bin boundary
+        let mut right_areas = [0.0; Self::BIN_COUNT];
+        let mut right_counts = [0; Self::BIN_COUNT];
+        let mut right_box: Option<AABB> = None;
+        let mut right_count = 0;
+        for bin in (1..Self::BIN_COUNT).rev() {
+            right_box = Self::merge(right_box, &bin_boxes[bin]);
+            right_count += counts[bin];
+            right_areas[bin] = right_box.as_ref().map_or(0.0, AABB::surface_area);
+            right_counts[bin] = right_count;
+        }
+
+        let parent_area = bbox.surface_area();
+        let mut best: Option<(usize, Real)> = None;
+        let mut left_box: Option<AABB> = None;
+        let mut left_count = 0;
+        for bin in 1..Self::BIN_COUNT {
+            left_box = Self::merge(left_box, &bin_boxes[bin - 1]);
+            left_count += counts[bin - 1];
+            if left_count == 0 || right_counts[bin] == 0 {
+                continue;
+            }
+
+            let left_area = left_box.as_ref().map_or(0.0, AABB::surface_area);
+            let cost = Self::TRAVERSAL_COST
+                + Self::INTERSECTION_COST
+                    * (left_area * left_count as Real
+                        + right_areas[bin] * right_counts[bin] as Real)
+                    / parent_area;
+            if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+                best = Some((bin, cost));
+            }
+        }
+
+        best.map(|(split, cost)| (move |i| bin_of(i) < split, cost))
     }
 
-    pub(crate) fn box_x_compare(a: &ObjectRef, b: &ObjectRef) -> Ordering {
-        Self::box_compare(a, b, &Axis::X)
+    fn merge(bbox: Option<AABB>, other: &Option<AABB>) -> Option<AABB> {
+        match (bbox, other) {
+            (Some(bbox), Some(other)) => Some(AABB::from_boxes(&bbox, other)),
+            (bbox, None) => bbox,
+            (None, other) => other.clone(),
+        }
     }
 
-    pub(crate) fn box_y_compare(a: &ObjectRef, b: &ObjectRef) -> Ordering {
-        Self::box_compare(a, b, &Axis::Y)
+    /// Moves the items that satisfy `predicate` to the front and returns how many there are.
+    fn partition(items: &mut [usize], predicate: impl Fn(usize) -> bool) -> usize {
+        let mut mid = 0;
+        for i in 0..items.len() {
+            if predicate(items[i]) {
+                items.swap(i, mid);
+                mid += 1;
+            }
+        }
+        mid
     }
+}
 
-    pub(crate) fn box_z_compare(a: &ObjectRef, b: &ObjectRef) -> Ordering {
-        Self::box_compare(a, b, &Axis::Z)
+/// Finds the closest hit in a hierarchy built by [`BVHBuilder`]. `hit_primitive` is called with
+/// the index of a primitive and the interval that is left to search.
+pub(crate) fn hit_nodes<'a>(
+    nodes: &[BVHNode],
+    ray: &Ray,
+    ray_t: &Interval,
+    hit_primitive: impl Fn(usize, &Interval) -> Option<HitRecord<'a>>,
+) -> Option<HitRecord<'a>> {
+    if nodes.is_empty() {
+        return None;
     }
 
-    pub(crate) fn box_compare(a: &ObjectRef, b: &ObjectRef, axis_index: &Axis) -> Ordering {
-        let a_axis_interval = a.bounding_box().axis_interval(axis_index);
-        let b_axis_interval = b.bounding_box().axis_interval(axis_index);
-        if a_axis_interval.min < b_axis_interval.min {
-            Ordering::Less
-        } else if b_axis_interval.min < a_axis_interval.min {
-            Ordering::Greater
+    let (origin, direction) = (ray.origin(), ray.direction());
+    let origin = [origin.x, origin.y, origin.z];
+    let inverse_direction = [1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z];
+
+    let mut closest: Option<HitRecord> = None;
+    let mut ray_t = ray_t.clone();
+    let mut stack = [0usize; BVHBuilder::MAX_DEPTH];
+    let mut stack_size = 0;
+    let mut current = 0;
+
+    loop {
+        metrics::increment_bvh_hit_attempt_count();
+        let node = &nodes[current];
+
+        if node.hit(&origin, &inverse_direction, &ray_t) {
+            if !node.is_leaf() {
+                // visit the child closer to the ray's origin first, so the hits found there
+                // can cull the farther one
+                let (near, far) = if inverse_direction[node.axis as usize] < 0.0 {
+                    metrics::increment_right_node_hit_attempt_count();
+                    (node.second_child(), current + 1)
+                } else {
+                    metrics::increment_left_node_hit_attempt_count();
+                    (current + 1, node.second_child())
+                };
+                stack[stack_size] = far;
+                stack_size += 1;
+                current = near;
+                continue;
+            }
+
+            for i in node.primitives() {
+                if let Some(record) = hit_primitive(i, &ray_t) {
+                    ray_t = Interval::new(ray_t.min, record.t());
+                    closest = Some(record);
+                }
+            }
         } else {
-            Ordering::Equal
+            metrics::increment_bvh_miss_count();
+        }
+
+        if stack_size == 0 {
+            break;
         }
+        stack_size -= 1;
+        current = stack[stack_size];
     }
 
-    pub(crate) fn left(&self) -> ObjectRef {
-        self.left.clone()
+    closest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BVHBuilder, BVHNode, SplitStrategy};
+    use crate::core::aabb::AABB;
+    use crate::core::math::{Point, Real};
+
+    /// Boxes of different sizes on a grid, plus a pile of them at the same spot that can't be
+    /// separated by their centroids.
+    fn boxes() -> Vec<AABB> {
+        let grid = (0..200).map(|i| {
+            let (x, y, z) = ((i % 7) as Real, (i / 7 % 5) as Real, (i / 35) as Real);
+            let size = 0.1 + (i % 3) as Real * 0.4;
+            AABB::from_points(
+                Point::new(x * 2.0, y * 3.0, z),
+                Point::new(x * 2.0 + size, y * 3.0 + size, z + size * 2.0),
+            )
+        });
+        let pile = (0..20)
+            .map(|_| AABB::from_points(Point::new(1.0, 1.0, 1.0), Point::new(2.0, 2.0, 2.0)));
+        grid.chain(pile).collect()
+    }
+
+    fn contains(outer: &AABB, inner: &AABB) -> bool {
+        [
+            (outer.x(), inner.x()),
+            (outer.y(), inner.y()),
+            (outer.z(), inner.z()),
+        ]
+        .iter()
+        .all(|(outer, inner)| outer.min <= inner.min && inner.max <= outer.max)
+    }
+
+    /// Visits the subtree at `index` and returns the index of the node that comes after it
+    fn check_node(
+        nodes: &[BVHNode],
+        index: usize,
+        boxes: &[AABB],
+        order: &[usize],
+        leaf_counts: &mut [usize],
+    ) -> usize {
+        let node = &nodes[index];
+        let bbox = node.bounding_box();
+
+        if node.is_leaf() {
+            for i in node.primitives() {
+                leaf_counts[order[i]] += 1;
+                assert!(contains(&bbox, &boxes[order[i]]));
+            }
+            return index + 1;
+        }
+
+        for child in [index + 1, node.second_child()] {
+            assert!(contains(&bbox, &nodes[child].bounding_box()));
+        }
+        let second_child = check_node(nodes, index + 1, boxes, order, leaf_counts);
+        assert_eq!(second_child, node.second_child());
+        check_node(nodes, second_child, boxes, order, leaf_counts)
+    }
+
+    #[test]
+    fn test_invariants() {
+        let boxes = boxes();
+        for strategy in [SplitStrategy::Median, SplitStrategy::Sah] {
+            let (nodes, order) = BVHBuilder::new(strategy, 4).build(&boxes);
+
+            let mut leaf_counts = vec![0; boxes.len()];
+            let end = check_node(&nodes, 0, &boxes, &order, &mut leaf_counts);
+
+            assert_eq!(end, nodes.len());
+            assert!(leaf_counts.iter().all(|&count| count == 1));
+        }
     }
 
-    pub(crate) fn right(&self) -> ObjectRef {
-        self.right.clone()
+    #[test]
+    fn test_no_boxes() {
+        let (nodes, order) = BVHBuilder::new(SplitStrategy::Sah, 4).build(&[]);
+        assert!(nodes.is_empty());
+        assert!(order.is_empty());
     }
 }
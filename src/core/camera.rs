@@ -1,23 +1,56 @@
 use crate::common::macros::generate_optional_setter;
+use crate::core::aov::{self, Pass, SceneIds};
 use crate::core::color::Color;
-use crate::core::hittables::Hittable;
+use crate::core::denoiser::Denoiser;
+use crate::core::environment::Environment;
+use crate::core::filter::{Filter, FilterSampler};
+use crate::core::framebuffer::Framebuffer;
+use crate::core::hittables::{HitRecord, Hittable};
+use crate::core::interrupt;
+use crate::core::lights::{Light, Lights};
+use crate::core::materials::{ScatterRecord, Scattered};
 use crate::core::math::interval::Interval;
 use crate::core::math::vector::{UnitVec3D, Vec3D, VecLike};
 use crate::core::math::{self, Point, Real};
 use crate::core::math::ray::Ray;
+use crate::core::output::{self, OutputFormat};
+use crate::core::pdf::Pdf;
+use crate::core::projection::Projection;
+use crate::core::sampler::Sampler;
+use crate::core::shutter::{Shutter, ShutterCurve};
+use crate::core::sky::Sky;
+use crate::core::tone_mapping::{ToneMapper, ToneMapping};
 use crate::diagnostics::stats;
 use crate::settings::Config;
+use image::ImageResult;
 use rayon::prelude::*;
-use std::fs::File;
-use std::io::{self, Write};
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Instant;
 
 pub(crate) struct Camera {
     image: Image,
     samples_per_pixel: u32,
+
+    /// With adaptive sampling, the samples every pixel gets before it can be considered
+    /// converged. `samples_per_pixel` becomes the maximum.
+    min_samples_per_pixel: u32,
+
+    /// The relative error under which a pixel stops getting samples, or 0 to give every pixel
+    /// `samples_per_pixel` samples
+    adaptive_threshold: Real,
+
     antialiasing: bool,
+    filter: FilterSampler,
     max_depth: u32,
+    projection: Projection,
     field_of_view: Real,
+
+    /// The height of the viewport of the orthographic projection, in world units
+    orthographic_height: Real,
+
     look_from: Point,
     look_at: Point,
     vup: Vec3D,
@@ -32,10 +65,30 @@ pub(crate) struct Camera {
 
     defocus_disk: DefocusDisk,
 
+    shutter: Shutter,
+
     background: Background,
 
     tile_width: u32,
     tile_height: u32,
+
+    tone_mapper: ToneMapper,
+
+    /// Every sample draws its random numbers from a stream derived from this seed
+    /// and the indices of the pixel and the sample.
+    seed: u64,
+
+    sampler: Sampler,
+
+    /// The extra images written next to the output file
+    passes: Vec<Pass>,
+
+    /// Denoises the final image, which is then written next to the noisy one
+    denoiser: Option<Denoiser>,
+
+    /// The passes rendered along with the image: the ones written, and the guides of the
+    /// denoiser
+    collected_passes: Vec<Pass>,
 }
 
 impl Camera {
@@ -43,22 +96,180 @@ impl Camera {
         CameraBuilder::new(config)
     }
 
-    pub(crate) fn render(&self, world: &Hittable, config: &Config) -> io::Result<()> {
+    pub(crate) fn render(
+        &self,
+        world: &Hittable,
+        lights: &Lights,
+        config: &Config,
+    ) -> io::Result<()> {
         let start = Instant::now();
-        let ppm_file = File::create(config.app().scene().output_file())?;
+
+        // resolve the output format before rendering so we don't waste a render on a bad path
+        let output_file = Path::new(config.app().scene().output_file());
+        let output_format = OutputFormat::from_path(output_file)?;
 
         stats::report(world);
 
-        // output the PPM contents
-        writeln!(
-            &ppm_file,
-            "P3\n{} {}\n255",
-            self.image.width,
-            self.image.height()
-        )?;
+        log::info!(
+            "Found {} emissive object(s) and {} light source(s) to sample directly.",
+            lights.objects().objects().len(),
+            lights.sources().len()
+        );
+
+        let progressive = config.app().scene().progressive();
+        let checkpoint_file = progressive
+            .checkpoint_file()
+            .or(progressive.resume_from())
+            .map(Path::new);
+        let (mut framebuffer, mut pass_buffers) = match progressive.resume_from() {
+            Some(resume_from) => self.resume(Path::new(resume_from))?,
+            None => (
+                Framebuffer::new(self.image.width, self.image.height()),
+                vec![
+                    Framebuffer::new(self.image.width, self.image.height());
+                    self.collected_passes.len()
+                ],
+            ),
+        };
+        let scene_ids = self
+            .collected_passes
+            .iter()
+            .any(|pass| matches!(pass, Pass::ObjectId | Pass::MaterialId))
+            .then(|| SceneIds::new(world));
+
+        let target = self.samples_per_pixel;
+        let samples_per_pass = progressive.samples_per_pass().max(1);
+        let mut last_checkpoint = Instant::now();
+
+        log::info!("Tile size: {} x {}", self.tile_width, self.tile_height);
+        log::info!(
+            "Reconstruction filter: {:?} with a radius of {} pixel(s)",
+            self.filter.filter(),
+            self.filter.radius()
+        );
+
+        while self.unfinished_pixel_count(&framebuffer) > 0 {
+            self.render_pass(
+                &mut framebuffer,
+                &mut pass_buffers,
+                samples_per_pass,
+                world,
+                lights,
+                scene_ids.as_ref(),
+            );
+
+            if interrupt::is_interrupted() {
+                log::warn!("Render interrupted. Writing what has been rendered so far.");
+                break;
+            }
+
+            let elapsed = start.elapsed().as_secs_f64();
+            let unfinished = self.unfinished_pixel_count(&framebuffer);
+            log::info!(
+                "Pass complete: {} to {} of {target} samples per pixel, {unfinished} pixel(s) \
+                left after {elapsed:.1}s.",
+                framebuffer.min_sample_count(),
+                framebuffer.max_sample_count()
+            );
+
+            let out_of_time = progressive
+                .time_limit()
+                .is_some_and(|time_limit| elapsed >= time_limit);
+            let done = out_of_time || unfinished == 0;
+
+            if let Some(checkpoint_file) = checkpoint_file {
+                let due =
+                    last_checkpoint.elapsed().as_secs_f64() >= progressive.checkpoint_interval();
+                if due && !done {
+                    self.save_checkpoint(checkpoint_file, &framebuffer, &pass_buffers)?;
+                    output::write_image(
+                        output_file,
+                        output_format,
+                        &framebuffer,
+                        &self.tone_mapper,
+                    )?;
+                    last_checkpoint = Instant::now();
+                }
+            }
+
+            if out_of_time {
+                log::info!("Time limit reached. Stopping the render.");
+                break;
+            }
+        }
 
-        let ppm_content = self.ppm_content(world);
-        writeln!(&ppm_file, "{}", ppm_content)?;
+        // the final checkpoint lets a finished render be refined later with more samples
+        if let Some(checkpoint_file) = checkpoint_file {
+            self.save_checkpoint(checkpoint_file, &framebuffer, &pass_buffers)?;
+        }
+
+        match &self.denoiser {
+            Some(denoiser) => {
+                let noisy_file = output::sibling_path(output_file, "noisy");
+                log::info!("Writing the noisy image to {}...", noisy_file.display());
+                output::write_image(&noisy_file, output_format, &framebuffer, &self.tone_mapper)?;
+
+                log::info!("Denoising...");
+                let denoised = denoiser.denoise(
+                    &framebuffer,
+                    &pass_buffers[self.pass_index(Pass::Albedo)],
+                    &pass_buffers[self.pass_index(Pass::Normal)],
+                );
+                match denoised {
+                    Some(denoised) => {
+                        log::info!("Writing the denoised image to {}...", output_file.display());
+                        output::write_image(
+                            output_file,
+                            output_format,
+                            &denoised,
+                            &self.tone_mapper,
+                        )?;
+                    }
+                    None => {
+                        log::warn!(
+                            "The albedo and normal passes miss some pixels, so the image can't be \
+                            denoised. Writing the noisy image to {}...",
+                            output_file.display()
+                        );
+                        output::write_image(
+                            output_file,
+                            output_format,
+                            &framebuffer,
+                            &self.tone_mapper,
+                        )?;
+                    }
+                }
+            }
+            None => {
+                log::info!("Writing the image to {}...", output_file.display());
+                output::write_image(output_file, output_format, &framebuffer, &self.tone_mapper)?;
+            }
+        }
+
+        if let Some(sample_count_file) = config.app().scene().sample_count_file() {
+            let sample_count_file = Path::new(sample_count_file);
+            log::info!(
+                "Writing the sample counts to {}...",
+                sample_count_file.display()
+            );
+            output::write_sample_counts(sample_count_file, &framebuffer)?;
+        }
+
+        for pass in &self.passes {
+            let pass_file = output::sibling_path(output_file, pass.name());
+            log::info!(
+                "Writing the {} pass to {}...",
+                pass.name(),
+                pass_file.display()
+            );
+            output::write_pass(
+                &pass_file,
+                output_format,
+                *pass,
+                &pass_buffers[self.pass_index(*pass)],
+                &self.tone_mapper,
+            )?;
+        }
 
         let duration = start.elapsed();
         log::info!("Rendering complete. Duration: {:?}", duration);
@@ -66,13 +277,120 @@ impl Camera {
         Ok(())
     }
 
-    fn ppm_content(&self, world: &Hittable) -> String {
-        let viewport = self.viewport();
-        let pixel_sample_scale = self.pixel_sample_scale();
+    /// Where the buffer of `pass` is among the collected passes
+    fn pass_index(&self, pass: Pass) -> usize {
+        self.collected_passes
+            .iter()
+            .position(|collected| *collected == pass)
+            .expect("the pass should be collected")
+    }
 
-        log::info!("Tile size: {} x {}", self.tile_width, self.tile_height);
+    /// The number of samples the pixel at `i`, `j` still needs. With adaptive sampling, pixels
+    /// stop getting samples once they have the minimum and their error is low enough.
+    fn remaining_samples(&self, framebuffer: &Framebuffer, i: u32, j: u32) -> u32 {
+        let taken = framebuffer.sample_count(i, j);
+        let remaining = self.samples_per_pixel.saturating_sub(taken);
+
+        let converged = self.adaptive_threshold > 0.0
+            && taken >= self.min_samples_per_pixel
+            && framebuffer
+                .relative_error(i, j)
+                .is_some_and(|error| error <= self.adaptive_threshold);
+        if converged {
+            0
+        } else {
+            remaining
+        }
+    }
+
+    fn unfinished_pixel_count(&self, framebuffer: &Framebuffer) -> usize {
+        (0..self.image.height)
+            .flat_map(|j| (0..self.image.width).map(move |i| (i, j)))
+            .filter(|&(i, j)| self.remaining_samples(framebuffer, i, j) > 0)
+            .count()
+    }
+
+    /// Writes the sums of the samples, the sums of their squared luminances and the sample counts
+    /// of the image to `checkpoint_file`, in version 2 of the `EANRAYFB` format. The buffers of
+    /// the passes go to its siblings: `render.ckpt` has its albedo in `render.albedo.ckpt`, for
+    /// example.
+    fn save_checkpoint(
+        &self,
+        checkpoint_file: &Path,
+        framebuffer: &Framebuffer,
+        pass_buffers: &[Framebuffer],
+    ) -> io::Result<()> {
+        log::info!("Writing a checkpoint to {}...", checkpoint_file.display());
+        framebuffer.save(checkpoint_file)?;
+        for (pass, pass_buffer) in self.collected_passes.iter().zip(pass_buffers) {
+            pass_buffer.save(&output::sibling_path(checkpoint_file, pass.name()))?;
+        }
+        Ok(())
+    }
 
-        log::info!("Splitting the screen into multiple tiles...");
+    /// Loads the image and the buffers of the passes saved by [`Self::save_checkpoint`]. A pass
+    /// that wasn't checkpointed only gets the samples taken from now on.
+    fn resume(&self, checkpoint_file: &Path) -> io::Result<(Framebuffer, Vec<Framebuffer>)> {
+        log::info!("Resuming from {}...", checkpoint_file.display());
+        let framebuffer = Framebuffer::load(checkpoint_file)?;
+        self.check_checkpoint_size(&framebuffer)?;
+
+        let pass_buffers = self
+            .collected_passes
+            .iter()
+            .map(|pass| {
+                let pass_file = output::sibling_path(checkpoint_file, pass.name());
+                let pass_buffer = Framebuffer::load(&pass_file).and_then(|pass_buffer| {
+                    self.check_checkpoint_size(&pass_buffer)?;
+                    Ok(pass_buffer)
+                });
+                pass_buffer.unwrap_or_else(|error| {
+                    log::warn!(
+                        "Could not resume the {} pass from {} ({error}). It starts over.",
+                        pass.name(),
+                        pass_file.display()
+                    );
+                    Framebuffer::new(self.image.width, self.image.height())
+                })
+            })
+            .collect();
+
+        log::info!(
+            "Resumed with {} samples per pixel.",
+            framebuffer.min_sample_count()
+        );
+        Ok((framebuffer, pass_buffers))
+    }
+
+    fn check_checkpoint_size(&self, framebuffer: &Framebuffer) -> io::Result<()> {
+        if (framebuffer.width(), framebuffer.height()) != (self.image.width, self.image.height()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "The checkpoint is {}x{}, but the image is {}x{}",
+                    framebuffer.width(),
+                    framebuffer.height(),
+                    self.image.width,
+                    self.image.height()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Adds up to `samples_per_pass` samples to every pixel of `framebuffer`
+    /// that hasn't reached the target yet or converged, and the same samples to the buffers of
+    /// the passes. After an interruption, the tiles that haven't been started are left as they
+    /// are.
+    fn render_pass(
+        &self,
+        framebuffer: &mut Framebuffer,
+        pass_buffers: &mut [Framebuffer],
+        samples_per_pass: u32,
+        world: &Hittable,
+        lights: &Lights,
+        scene_ids: Option<&SceneIds>,
+    ) {
         let tiles: Vec<(u32, u32)> = (0..self.image.height)
             .step_by(self.tile_height as usize)
             .flat_map(|y| {
@@ -82,84 +400,198 @@ impl Camera {
             })
             .collect();
 
-        log::info!("Rendering {} tiles...", tiles.len());
-        let pixel_tiles = tiles
+        log::debug!("Rendering {} tiles...", tiles.len());
+        let accumulated: &Framebuffer = framebuffer;
+        let rendered_tiles: Vec<(u32, u32, Framebuffer, Vec<Framebuffer>)> = tiles
             .into_par_iter()
-            .fold(
-                || Vec::new(),
-                |mut local_buffer, (x, y)| {
-                    for j in y..(y + self.tile_height).min(self.image.height) {
-                        for i in x..(x + self.tile_width).min(self.image.width) {
-                            let pixel_color =
-                                self.pixel_color(i, j, pixel_sample_scale, &viewport, world);
-                            local_buffer.push((i, j, pixel_color));
-                        }
-                    }
-                    log::info!("Tile {x}, {y} rendering complete.");
-                    local_buffer
-                },
-            )
-            .reduce(
-                || Vec::new(),
-                |mut acc, buffer| {
-                    acc.extend(buffer);
-                    acc
-                },
-            );
+            .filter_map(|(x, y)| {
+                // the remaining tiles are skipped, and the ones in progress get finished
+                if interrupt::is_interrupted() {
+                    return None;
+                }
 
-        log::info!("Merging tiles into one buffer...");
-        let mut pixels: Vec<Vec<String>> =
-            vec![vec![String::new(); self.image.width as usize]; self.image.height as usize];
+                let (tile, pass_tiles) =
+                    self.render_tile(x, y, world, lights, scene_ids, |i, j| {
+                        let taken = accumulated.sample_count(i, j);
+                        let remaining = self.remaining_samples(accumulated, i, j);
+                        taken..taken + remaining.min(samples_per_pass)
+                    });
+                log::debug!("Tile {x}, {y} rendering complete.");
+                Some((x, y, tile, pass_tiles))
+            })
+            .collect();
 
-        for (x, y, color) in pixel_tiles {
-            pixels[y as usize][x as usize] = format!("{} ", color.to_bytes_string());
+        for (x, y, tile, pass_tiles) in rendered_tiles {
+            framebuffer.merge_at(x, y, &tile);
+            for (pass_buffer, pass_tile) in pass_buffers.iter_mut().zip(&pass_tiles) {
+                pass_buffer.merge_at(x, y, pass_tile);
+            }
         }
-        pixels
-            .iter()
-            .map(|row| row.join(""))
-            .collect::<Vec<_>>()
-            .join("")
     }
 
-    fn pixel_color(
+    /// Renders the tile whose upper-left pixel is at `x`, `y` into its own framebuffer, and
+    /// one more for each pass. `samples` gives the indices of the samples to take for each pixel
+    /// of the image.
+    fn render_tile<F>(
         &self,
-        i: u32,
-        j: u32,
-        pixel_sample_scale: f64,
-        viewport: &Viewport,
+        x: u32,
+        y: u32,
         world: &Hittable,
+        lights: &Lights,
+        scene_ids: Option<&SceneIds>,
+        samples: F,
+    ) -> (Framebuffer, Vec<Framebuffer>)
+    where
+        F: Fn(u32, u32) -> Range<u32>,
+    {
+        let viewport = &self.viewport();
+        let tile_width = self.tile_width.min(self.image.width - x);
+        let tile_height = self.tile_height.min(self.image.height - y);
+        let mut tile = Framebuffer::new(tile_width, tile_height);
+        let mut pass_tiles = vec![tile.clone(); self.collected_passes.len()];
+        let needs_first_hit = self.collected_passes.iter().any(Pass::is_geometric);
+
+        for j in 0..tile_height {
+            for i in 0..tile_width {
+                let pixel_seed = math::mix_seed(self.seed, self.pixel_index(x + i, y + j));
+                for sample_index in samples(x + i, y + j) {
+                    math::seed_rng(math::mix_seed(pixel_seed, sample_index as u64));
+                    self.sampler
+                        .start_sample(pixel_seed, sample_index, self.samples_per_pixel);
+                    let Some((ray, weight)) = self.get_ray(x + i, y + j, viewport) else {
+                        // outside the image circle of a fisheye lens
+                        tile.add_sample(i, j, &Color::black());
+                        for pass_tile in &mut pass_tiles {
+                            pass_tile.add_sample(i, j, &Color::black());
+                        }
+                        continue;
+                    };
+                    let radiance =
+                        self.ray_color(&ray, self.max_depth, world, lights, EmissionWeights::FULL);
+                    tile.add_sample(i, j, &(radiance.total() * weight));
+
+                    if !self.collected_passes.is_empty() {
+                        let first_hit = if needs_first_hit {
+                            Self::first_hit(&ray, world)
+                        } else {
+                            None
+                        };
+                        for (pass, pass_tile) in self.collected_passes.iter().zip(&mut pass_tiles) {
+                            let value = self.pass_value(
+                                *pass,
+                                first_hit.as_ref(),
+                                &radiance,
+                                weight,
+                                scene_ids,
+                            );
+                            pass_tile.add_sample(i, j, &value);
+                        }
+                    }
+                }
+            }
+        }
+        Sampler::end_sample();
+        (tile, pass_tiles)
+    }
+
+    /// The closest hit of a camera ray
+    fn first_hit<'a>(ray: &Ray, world: &'a Hittable) -> Option<HitRecord<'a>> {
+        world.hit(ray, &Interval::new(0.001, math::INFINITY))
+    }
+
+    /// The value a sample contributes to `pass`. The lighting passes are weighted by the
+    /// reconstruction filter like the beauty image, so they add up to it.
+    fn pass_value(
+        &self,
+        pass: Pass,
+        first_hit: Option<&HitRecord>,
+        radiance: &Radiance,
+        weight: Real,
+        scene_ids: Option<&SceneIds>,
     ) -> Color {
-        if self.antialiasing {
-            // compute the average color from the sample rays
-            (0..self.samples_per_pixel)
-                .map(|_| self.ray_color(&self.get_ray(i, j, &viewport), self.max_depth, world))
-                .fold(Color::black(), |acc, color| acc + color)
-                * pixel_sample_scale
-        } else {
-            let pixel_center = viewport.pixel_00_loc()
-                + (viewport.pixel_delta_horizontal() * i as Real)
-                + (viewport.pixel_delta_vertical() * j as Real);
-            let ray_direction = pixel_center - self.center();
-            let ray = Ray::new(self.center().clone(), ray_direction);
-            self.ray_color(&ray, self.max_depth, world)
-        }
-    }
-
-    /// Returns a ray directed towards a randomly sampled point around the pixel at i, j
-    fn get_ray(&self, i: u32, j: u32, viewport: &Viewport) -> Ray {
-        let offset = Self::sample_square();
-        let pixel_sample = viewport.pixel_00_loc()
-            + (viewport.pixel_delta_horizontal() * (offset.x + i as Real))
-            + (viewport.pixel_delta_vertical() * (offset.y + j as Real));
-        let origin = if self.defocus_angle <= 0.0 {
-            self.center().clone()
+        match (pass, first_hit) {
+            (Pass::Direct, _) => radiance.direct.clone() * weight,
+            (Pass::Indirect, _) => radiance.indirect.clone() * weight,
+            (Pass::Emission, _) => radiance.emitted.clone() * weight,
+            (_, None) => Color::black(),
+            (Pass::Depth, Some(record)) => {
+                let depth = -(record.p() - self.center()).dot(&self.out.0);
+                Color::from_scalar(depth)
+            }
+            (Pass::Normal, Some(record)) => {
+                let normal = &record.normal().0;
+                Color::new(normal.x, normal.y, normal.z)
+            }
+            (Pass::Albedo, Some(record)) => record.material().albedo(record),
+            (Pass::Uv, Some(record)) => Color::new(record.u(), record.v(), 0.0),
+            (Pass::ObjectId, Some(record)) => {
+                aov::id_color(scene_ids.map_or(0, |ids| ids.object(record.material())))
+            }
+            (Pass::MaterialId, Some(record)) => {
+                aov::id_color(scene_ids.map_or(0, |ids| ids.material(record.material())))
+            }
+        }
+    }
+
+    fn pixel_index(&self, i: u32, j: u32) -> u64 {
+        j as u64 * self.image.width as u64 + i as u64
+    }
+
+    /// Returns a ray through a point around the pixel at i, j, sampled according to the
+    /// reconstruction filter, together with the weight of its sample. Without antialiasing, the
+    /// ray goes through the center of the pixel instead. Returns `None` if the point is outside
+    /// the image circle of a fisheye lens.
+    fn get_ray(&self, i: u32, j: u32, viewport: &Viewport) -> Option<(Ray, Real)> {
+        let (offset_x, offset_y, weight) = if self.antialiasing {
+            let (u, v) = math::random_pair();
+            self.filter.sample(u, v)
         } else {
-            self.defocus_disk_sample()
+            (0.0, 0.0, 1.0)
+        };
+
+        let (origin, direction) = match self.projection {
+            Projection::Perspective => {
+                let pixel_sample = viewport.pixel_00_loc()
+                    + (viewport.pixel_delta_horizontal() * (offset_x + i as Real))
+                    + (viewport.pixel_delta_vertical() * (offset_y + j as Real));
+                let origin = if self.defocus_angle <= 0.0 {
+                    self.center().clone()
+                } else {
+                    self.defocus_disk_sample()
+                };
+                let direction = pixel_sample - &origin;
+                (origin, direction)
+            }
+            Projection::Orthographic => {
+                // the viewport goes through the camera, and all the rays leave it straight ahead
+                let origin = viewport.pixel_00_loc()
+                    + (viewport.pixel_delta_horizontal() * (offset_x + i as Real))
+                    + (viewport.pixel_delta_vertical() * (offset_y + j as Real));
+                (origin, -&self.out.0)
+            }
+            Projection::FisheyeEquidistant
+            | Projection::FisheyeEquisolid
+            | Projection::Equirectangular => {
+                let u = (i as Real + 0.5 + offset_x) / self.image.width as Real;
+                let v = (j as Real + 0.5 + offset_y) / self.image.height as Real;
+                let direction = self.projection.direction(
+                    u,
+                    v,
+                    self.field_of_view,
+                    self.image.actual_aspect_ratio(),
+                )?;
+
+                // the camera looks along -out
+                let direction = &self.right.0 * direction.x
+                    + &self.up.0 * direction.y
+                    + &self.out.0 * -direction.z;
+                (self.center().clone(), direction)
+            }
         };
-        let direction = pixel_sample - &origin;
-        let ray_time = math::random_real();
+        let row = (j as Real + 0.5 + offset_y) / self.image.height as Real;
+        let ray_time = self.shutter.sample_time(math::random_real(), row);
 
-        Ray::new_timed(origin, direction, ray_time)
+        Some((Ray::new_timed(origin, direction, ray_time), weight))
     }
 
     fn defocus_disk_sample(&self) -> Point {
@@ -167,46 +599,231 @@ impl Camera {
         self.center() + (&self.defocus_disk.horizontal * p.x) + (&self.defocus_disk.vertical * p.y)
     }
 
-    /// A vector to a random point within half the unit square.
-    fn sample_square() -> Vec3D {
-        Vec3D::new(math::random_real() - 0.5, math::random_real() - 0.5, 0.0)
-    }
-
-    fn ray_color(&self, ray: &Ray, depth: u32, world: &Hittable) -> Color {
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        depth: u32,
+        world: &Hittable,
+        lights: &Lights,
+        emission_weights: EmissionWeights,
+    ) -> Radiance {
         if depth <= 0 {
-            Color::black()
+            Radiance::black()
         } else if let Some(record) = world.hit(ray, &mut Interval::new(0.001, math::INFINITY)) {
-            let color_from_emission = record
+            let emitted = record
                 .material()
-                .emitted(record.u(), record.v(), record.p());
-
-            if let Some((scattered, attenuation)) = record.material().scatter(ray, &record) {
-                let color_from_scatter = self.ray_color(&scattered, depth - 1, world) * attenuation;
-                color_from_emission + color_from_scatter
-            } else {
-                color_from_emission
+                .emitted(record.u(), record.v(), record.p())
+                * emission_weights.objects;
+
+            let scattered = match record.material().scatter(ray, &record) {
+                Some(ScatterRecord {
+                    attenuation,
+                    scattered: Scattered::Specular(scattered),
+                }) => self
+                    .ray_color(&scattered, depth - 1, world, lights, EmissionWeights::FULL)
+                    .bounced()
+                    .attenuated(&attenuation),
+                Some(ScatterRecord {
+                    attenuation,
+                    scattered: Scattered::Pdf(pdf),
+                }) => self
+                    .ray_color_from_pdf(ray, &record, &pdf, depth, world, lights)
+                    .attenuated(&attenuation),
+                None => Radiance::black(),
+            };
+            Radiance {
+                emitted,
+                ..scattered
             }
         } else {
-            match &self.background {
-                Background::Color(color) => color.clone(),
-                Background::Lerp { start, end } => {
-                    let unit_direction = ray.direction().to_unit();
-                    let a = math::normalize_to_01(unit_direction.y);
-                    math::lerp(start, end, a)
-                }
+            Radiance {
+                emitted: self.background.value(ray.direction()) * emission_weights.background
+                    + self.background.sun_value(ray.direction()) * emission_weights.sun,
+                ..Radiance::black()
             }
         }
     }
 
+    /// Follows a ray scattered according to the material's `pdf`. When there are lights,
+    /// the direct lighting comes from a separate sample towards them (next-event estimation),
+    /// and the emission found by the scattered ray gets weighted against that light sampling
+    /// strategy (multiple importance sampling). The result still has to be attenuated, and
+    /// has nothing emitted at the hit point itself.
+    fn ray_color_from_pdf(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        pdf: &Pdf,
+        depth: u32,
+        world: &Hittable,
+        lights: &Lights,
+    ) -> Radiance {
+        let scattered = Ray::new_timed(record.p().clone(), pdf.generate(), ray.time());
+        let pdf_value = pdf.value(scattered.direction());
+        let scattering = record.material().scattering(ray, record, &scattered);
+
+        if pdf_value <= 0.0 {
+            return Radiance::black();
+        }
+
+        let (direct, weight) = if lights.objects().is_empty() {
+            (Color::black(), 1.0)
+        } else {
+            let light_pdf = Pdf::hittable(lights.objects(), record.p().clone(), ray.time());
+            let weight = math::power_heuristic(pdf_value, light_pdf.value(scattered.direction()));
+            let direct = self.sample_lights(ray, record, pdf, &light_pdf, world);
+            (direct, weight)
+        };
+        let (environment_direct, background_weight) = match self.background.environment() {
+            Some(environment) => {
+                let weight = math::power_heuristic(
+                    pdf_value,
+                    environment.pdf_value(scattered.direction()),
+                );
+                let direct = self.sample_environment(ray, record, pdf, environment, world);
+                (direct, weight)
+            }
+            None => (Color::black(), 1.0),
+        };
+        let direct = direct
+            + environment_direct
+            + self.sample_light_sources(ray, record, lights, world);
+
+        let emission_weights = EmissionWeights {
+            objects: weight,
+            background: background_weight,
+            sun: 0.0,
+        };
+        let bounced = self
+            .ray_color(&scattered, depth - 1, world, lights, emission_weights)
+            .bounced()
+            .attenuated(&(scattering / pdf_value));
+        Radiance {
+            direct: bounced.direct + direct,
+            ..bounced
+        }
+    }
+
+    /// Samples a direction from `light_pdf` and returns the light that arrives at the hit point
+    /// from that direction, weighted against the material's own scattering `pdf`.
+    fn sample_lights(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        pdf: &Pdf,
+        light_pdf: &Pdf,
+        world: &Hittable,
+    ) -> Color {
+        let shadow_ray = Ray::new_timed(record.p().clone(), light_pdf.generate(), ray.time());
+        let light_pdf_value = light_pdf.value(shadow_ray.direction());
+        let scattering = record.material().scattering(ray, record, &shadow_ray);
+
+        if light_pdf_value <= 0.0 || scattering.is_black() {
+            return Color::black();
+        }
+
+        // Whatever the shadow ray hits first is what actually lights the point
+        match world.hit(&shadow_ray, &Interval::new(0.001, math::INFINITY)) {
+            Some(light_record) => {
+                let emitted = light_record.material().emitted(
+                    light_record.u(),
+                    light_record.v(),
+                    light_record.p(),
+                );
+                let weight =
+                    math::power_heuristic(light_pdf_value, pdf.value(shadow_ray.direction()));
+                emitted * scattering * (weight / light_pdf_value)
+            }
+            None => Color::black(),
+        }
+    }
+
+    /// Samples a direction from the `environment` of the background and returns the light that
+    /// arrives at the hit point from it, unless something is in the way. Weighted against the
+    /// material's own scattering `pdf`, like [`Self::sample_lights`].
+    fn sample_environment(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        pdf: &Pdf,
+        environment: &Environment,
+        world: &Hittable,
+    ) -> Color {
+        let shadow_ray =
+            Ray::new_timed(record.p().clone(), environment.random_direction(), ray.time());
+        let environment_pdf_value = environment.pdf_value(shadow_ray.direction());
+        let scattering = record.material().scattering(ray, record, &shadow_ray);
+
+        if environment_pdf_value <= 0.0 || scattering.is_black() {
+            return Color::black();
+        }
+
+        let occluded = world
+            .hit(&shadow_ray, &Interval::new(0.001, math::INFINITY))
+            .is_some();
+        if occluded {
+            return Color::black();
+        }
+
+        let weight =
+            math::power_heuristic(environment_pdf_value, pdf.value(shadow_ray.direction()));
+        self.background.value(shadow_ray.direction())
+            * scattering
+            * (weight / environment_pdf_value)
+    }
+
+    /// Returns the light that arrives at the hit point from every light source without
+    /// geometry, and from the sun of the sky. Those can't be found by the scattered rays, so
+    /// there is nothing to weight against.
+    fn sample_light_sources(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        lights: &Lights,
+        world: &Hittable,
+    ) -> Color {
+        let mut direct = Color::black();
+        for sample in lights
+            .sources()
+            .iter()
+            .chain(self.background.sun())
+            .filter_map(|light| light.sample(record.p()))
+        {
+            let shadow_ray = Ray::new_timed(record.p().clone(), sample.direction, ray.time());
+            let scattering = record.material().scattering(ray, record, &shadow_ray);
+            if scattering.is_black() {
+                continue;
+            }
+
+            let occluded = world
+                .hit(&shadow_ray, &Interval::new(0.001, sample.distance))
+                .is_some();
+            if !occluded {
+                direct = direct + sample.irradiance * scattering;
+            }
+        }
+        direct
+    }
+
     fn viewport(&self) -> Viewport {
-        let theta = math::degrees_to_radians(self.field_of_view);
-        let h = Real::tan(theta / 2.0);
-        let height = 2.0 * h * self.focus_distance;
-        Viewport::new(height, &self, &self.image)
+        match self.projection {
+            Projection::Orthographic => {
+                Viewport::new(self.orthographic_height, 0.0, self, &self.image)
+            }
+            _ => {
+                let height =
+                    Self::perspective_viewport_height(self.field_of_view, self.focus_distance);
+                Viewport::new(height, self.focus_distance, self, &self.image)
+            }
+        }
     }
 
-    pub(crate) fn pixel_sample_scale(&self) -> Real {
-        1.0 / self.samples_per_pixel as Real
+    /// The height of the viewport of a perspective projection at `focus_distance`, which is what
+    /// the orthographic projection shows by default.
+    fn perspective_viewport_height(field_of_view: Real, focus_distance: Real) -> Real {
+        let theta = math::degrees_to_radians(field_of_view);
+        let h = Real::tan(theta / 2.0);
+        2.0 * h * focus_distance
     }
 
     fn center(&self) -> &Point {
@@ -214,6 +831,74 @@ impl Camera {
     }
 }
 
+/// The light carried back along a ray, split by the number of times it bounced before
+/// reaching the origin of the ray.
+struct Radiance {
+    /// Emitted at the hit point (or coming from the background), without any bounce
+    emitted: Color,
+
+    /// Bounced once, at the hit point
+    direct: Color,
+
+    /// Bounced two or more times
+    indirect: Color,
+}
+
+impl Radiance {
+    fn black() -> Self {
+        Self {
+            emitted: Color::black(),
+            direct: Color::black(),
+            indirect: Color::black(),
+        }
+    }
+
+    fn total(&self) -> Color {
+        &self.emitted + &self.direct + &self.indirect
+    }
+
+    /// The same light, seen from one bounce earlier along the path
+    fn bounced(self) -> Self {
+        Self {
+            emitted: Color::black(),
+            direct: self.emitted,
+            indirect: self.direct + self.indirect,
+        }
+    }
+
+    fn attenuated(self, attenuation: &Color) -> Self {
+        Self {
+            emitted: self.emitted * attenuation.clone(),
+            direct: self.direct * attenuation.clone(),
+            indirect: self.indirect * attenuation.clone(),
+        }
+    }
+}
+
+/// How much of the light emitted by what a ray finds counts. When it was also sampled directly
+/// from the previous hit, the two strategies get weighted against each other.
+#[derive(Clone, Copy)]
+struct EmissionWeights {
+    /// For the emissive objects
+    objects: Real,
+
+    /// For the background
+    background: Real,
+
+    /// For the disk of the sun, which is only ever sampled as a light source, so it is either
+    /// all or nothing
+    sun: Real,
+}
+
+impl EmissionWeights {
+    /// Nothing was sampled directly, so all of it counts
+    const FULL: Self = Self {
+        objects: 1.0,
+        background: 1.0,
+        sun: 1.0,
+    };
+}
+
 pub(crate) struct CameraBuilder {
     optionals: OptionalFields,
     config: &'static Config,
@@ -260,14 +945,41 @@ impl CameraBuilder {
         // of two perpendicular unit vectors
         let up = UnitVec3D(out.cross(&right));
 
+        let field_of_view = optionals.field_of_view.unwrap_or(defaults.field_of_view());
+        let focus_distance = optionals
+            .focus_distance
+            .unwrap_or(defaults.focus_distance());
+
         let mut camera = Camera {
             image: optionals.image.clone().unwrap_or(Image::new(100, 1.0)),
             samples_per_pixel: optionals
                 .samples_per_pixel
                 .unwrap_or(defaults.samples_per_pixel()),
+            min_samples_per_pixel: optionals
+                .min_samples_per_pixel
+                .unwrap_or(defaults.min_samples_per_pixel()),
+            adaptive_threshold: optionals
+                .adaptive_threshold
+                .unwrap_or(defaults.adaptive_threshold()),
             antialiasing: optionals.antialiasing.unwrap_or(defaults.antialiasing()),
+            filter: {
+                let filter = optionals.filter.unwrap_or(defaults.filter());
+                let radius = optionals
+                    .filter_radius
+                    .or(defaults.filter_radius())
+                    .unwrap_or(filter.default_radius());
+                FilterSampler::new(filter, radius)
+            },
             max_depth: optionals.max_depth.unwrap_or(defaults.max_depth()),
-            field_of_view: optionals.field_of_view.unwrap_or(defaults.field_of_view()),
+            projection: optionals.projection.unwrap_or(defaults.projection()),
+            field_of_view,
+            orthographic_height: optionals
+                .orthographic_height
+                .or(defaults.orthographic_height())
+                .unwrap_or(Camera::perspective_viewport_height(
+                    field_of_view,
+                    focus_distance,
+                )),
             look_from,
             look_at,
             vup,
@@ -275,10 +987,16 @@ impl CameraBuilder {
             right,
             up,
             defocus_angle: optionals.defocus_angle.unwrap_or(defaults.defocus_angle()),
-            focus_distance: optionals
-                .focus_distance
-                .unwrap_or(defaults.focus_distance()),
+            focus_distance,
             defocus_disk: DefocusDisk::empty(),
+            shutter: Shutter::new(
+                optionals.shutter_open.unwrap_or(defaults.shutter_open()),
+                optionals.shutter_close.unwrap_or(defaults.shutter_close()),
+                optionals.shutter_curve.unwrap_or(defaults.shutter_curve()),
+                optionals
+                    .rolling_shutter_duration
+                    .unwrap_or(defaults.rolling_shutter_duration()),
+            ),
             background: optionals
                 .background
                 .clone()
@@ -287,40 +1005,110 @@ impl CameraBuilder {
                 ))),
             tile_width: optionals.tile_width.unwrap_or(defaults.tile_width()),
             tile_height: optionals.tile_height.unwrap_or(defaults.tile_height()),
+            tone_mapper: ToneMapper::new(
+                optionals.tone_mapping.unwrap_or(defaults.tone_mapping()),
+                optionals.exposure.unwrap_or(defaults.exposure()),
+                optionals.white_point.unwrap_or(defaults.white_point()),
+            ),
+            seed: optionals.seed.unwrap_or(defaults.seed()),
+            sampler: optionals.sampler.unwrap_or(defaults.sampler()),
+            passes: optionals
+                .passes
+                .clone()
+                .unwrap_or(defaults.passes().to_vec()),
+            denoiser: optionals
+                .denoise
+                .unwrap_or(defaults.denoise())
+                .then(Denoiser::default),
+            collected_passes: vec![],
         };
 
+        camera.collected_passes = camera.passes.clone();
+        if camera.denoiser.is_some() {
+            for guide in [Pass::Albedo, Pass::Normal] {
+                if !camera.collected_passes.contains(&guide) {
+                    camera.collected_passes.push(guide);
+                }
+            }
+        }
+
         camera.defocus_disk = DefocusDisk::from_camera(&camera);
         camera
     }
 
     generate_optional_setter!(optionals, image, Image);
     generate_optional_setter!(optionals, samples_per_pixel, u32);
+    generate_optional_setter!(optionals, min_samples_per_pixel, u32);
+    generate_optional_setter!(optionals, adaptive_threshold, Real);
     generate_optional_setter!(optionals, antialiasing, bool);
+    generate_optional_setter!(optionals, filter, Filter);
     generate_optional_setter!(optionals, max_depth, u32);
+    generate_optional_setter!(optionals, projection, Projection);
     generate_optional_setter!(optionals, field_of_view, Real);
     generate_optional_setter!(optionals, look_from, Point);
     generate_optional_setter!(optionals, look_at, Point);
     generate_optional_setter!(optionals, vup, Vec3D);
     generate_optional_setter!(optionals, defocus_angle, Real);
     generate_optional_setter!(optionals, focus_distance, Real);
+    generate_optional_setter!(optionals, shutter_open, Real);
+    generate_optional_setter!(optionals, shutter_close, Real);
+    generate_optional_setter!(optionals, shutter_curve, ShutterCurve);
+    generate_optional_setter!(optionals, rolling_shutter_duration, Real);
     generate_optional_setter!(optionals, background, Background);
+    generate_optional_setter!(optionals, tone_mapping, ToneMapping);
+    generate_optional_setter!(optionals, exposure, Real);
+    generate_optional_setter!(optionals, white_point, Real);
+    generate_optional_setter!(optionals, seed, u64);
+    generate_optional_setter!(optionals, sampler, Sampler);
+    generate_optional_setter!(optionals, passes, Vec<Pass>);
+    generate_optional_setter!(optionals, denoise, bool);
+
+    /// `None` leaves it to the config, and then to the radius the filter is designed for
+    pub(crate) fn filter_radius(&mut self, filter_radius: Option<Real>) -> &mut Self {
+        self.optionals.filter_radius = filter_radius;
+        self
+    }
+
+    /// `None` leaves it to the config, and then to the height the perspective projection shows
+    /// at the focus distance
+    pub(crate) fn orthographic_height(&mut self, orthographic_height: Option<Real>) -> &mut Self {
+        self.optionals.orthographic_height = orthographic_height;
+        self
+    }
 }
 
 #[derive(Default)]
 pub(crate) struct OptionalFields {
     image: Option<Image>,
     samples_per_pixel: Option<u32>,
+    min_samples_per_pixel: Option<u32>,
+    adaptive_threshold: Option<Real>,
     antialiasing: Option<bool>,
+    filter: Option<Filter>,
+    filter_radius: Option<Real>,
     max_depth: Option<u32>,
+    projection: Option<Projection>,
     field_of_view: Option<Real>,
+    orthographic_height: Option<Real>,
     look_from: Option<Point>,
     look_at: Option<Point>,
     vup: Option<Vec3D>,
     defocus_angle: Option<Real>,
     focus_distance: Option<Real>,
+    shutter_open: Option<Real>,
+    shutter_close: Option<Real>,
+    shutter_curve: Option<ShutterCurve>,
+    rolling_shutter_duration: Option<Real>,
     background: Option<Background>,
     tile_width: Option<u32>,
     tile_height: Option<u32>,
+    tone_mapping: Option<ToneMapping>,
+    exposure: Option<Real>,
+    white_point: Option<Real>,
+    seed: Option<u64>,
+    sampler: Option<Sampler>,
+    passes: Option<Vec<Pass>>,
+    denoise: Option<bool>,
 }
 
 #[derive(Clone)]
@@ -379,14 +1167,15 @@ struct Viewport {
 }
 
 impl Viewport {
-    pub(crate) fn new(height: f64, camera: &Camera, image: &Image) -> Viewport {
+    /// A viewport `height` units high, `distance` units in front of the camera
+    pub(crate) fn new(height: f64, distance: f64, camera: &Camera, image: &Image) -> Viewport {
         let width = height * image.actual_aspect_ratio();
         let left_to_right = width * &camera.right.0;
         let top_to_bottom = height * &(-&camera.up.0);
         let pixel_delta_horizontal = &left_to_right / image.width as Real;
         let pixel_delta_vertical = &top_to_bottom / image.height as Real;
         let upper_left = camera.center()
-            - (camera.focus_distance * &camera.out.0)
+            - (distance * &camera.out.0)
             - &left_to_right / 2.0
             - &top_to_bottom / 2.0;
         let pixel_00_loc = &upper_left + (&pixel_delta_horizontal + &pixel_delta_vertical) * 0.5;
@@ -464,6 +1253,12 @@ impl DefocusDisk {
 pub(crate) enum Background {
     Color(Color),
     Lerp { start: Color, end: Color },
+
+    /// An environment map, which also lights the scene like any other light
+    Environment(Arc<Environment>),
+
+    /// A physically-based sky, with its sun
+    Sky(Arc<Sky>),
 }
 
 impl Background {
@@ -474,4 +1269,156 @@ impl Background {
     pub(crate) fn from_lerp(start: Color, end: Color) -> Self {
         Self::Lerp { start, end }
     }
+
+    /// Loads an equirectangular environment map, turned by `rotation` degrees around the
+    /// vertical axis and scaled by `intensity`.
+    pub(crate) fn from_environment(
+        path: &Path,
+        rotation: Real,
+        intensity: Real,
+    ) -> ImageResult<Self> {
+        let environment = Environment::from_path(path, rotation, intensity)?;
+        Ok(Self::Environment(Arc::new(environment)))
+    }
+
+    pub(crate) fn from_sky(sky: Sky) -> Self {
+        Self::Sky(Arc::new(sky))
+    }
+
+    /// The light coming from `direction`, for the rays that hit nothing
+    fn value(&self, direction: &Vec3D) -> Color {
+        match self {
+            Self::Color(color) => color.clone(),
+            Self::Lerp { start, end } => {
+                let unit_direction = direction.to_unit();
+                let a = math::normalize_to_01(unit_direction.y);
+                math::lerp(start, end, a)
+            }
+            Self::Environment(environment) => environment.value(direction),
+            Self::Sky(sky) => sky.value(direction),
+        }
+    }
+
+    /// The light of the sun, if `direction` points at it. Kept apart from [`Self::value`]
+    /// because the sun is sampled as a light source instead.
+    fn sun_value(&self, direction: &Vec3D) -> Color {
+        match self {
+            Self::Sky(sky) => sky.sun_value(direction),
+            _ => Color::black(),
+        }
+    }
+
+    /// What the background is sampled through, if it lights the scene
+    fn environment(&self) -> Option<&Environment> {
+        match self {
+            Self::Environment(environment) => Some(environment),
+            Self::Sky(sky) => Some(sky.environment()),
+            _ => None,
+        }
+    }
+
+    fn sun(&self) -> Option<&Light> {
+        match self {
+            Self::Sky(sky) => sky.sun(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Camera, Image};
+    use crate::core::framebuffer::Framebuffer;
+    use crate::core::lights::Lights;
+    use crate::core::materials::{Dielectric, DiffuseLight, Lambertian, MaterialKind};
+    use crate::core::math::{Point, Vec3D};
+    use crate::core::shapes::Sphere;
+    use crate::core::shapes::plane::Plane;
+    use crate::core::shapes::quadrics::Quadric;
+    use crate::core::{Color, Hittable, HittableList, Material};
+    use crate::settings::Config;
+
+    fn config() -> &'static Config {
+        let config = config::Config::builder()
+            .add_source(config::File::with_name("config"))
+            .build()
+            .and_then(config::Config::try_deserialize)
+            .unwrap();
+        Box::leak(Box::new(config))
+    }
+
+    /// A diffuse sphere and a glass one on a floor, lit by a sphere light
+    fn world() -> Hittable {
+        let sphere = |center: Point, radius, material| {
+            Hittable::Quadric(Quadric::Sphere(Sphere::stationary(
+                center, radius, material,
+            )))
+        };
+        let diffuse = |r, g, b| Material::new(MaterialKind::Lambertian(Lambertian::from_albedo(Color::new(r, g, b))));
+
+        let mut objects = HittableList::empty();
+        objects.add(Hittable::Plane(Plane::new(
+            Point::new(0.0, 0.0, 0.0),
+            Vec3D::new(0.0, 1.0, 0.0).to_unit(),
+            diffuse(0.5, 0.5, 0.5),
+        )));
+        objects.add(sphere(
+            Point::new(-0.6, 0.5, 0.0),
+            0.5,
+            diffuse(0.8, 0.2, 0.2),
+        ));
+        objects.add(sphere(
+            Point::new(0.6, 0.5, 0.0),
+            0.5,
+            Material::new(MaterialKind::Dielectric(Dielectric::new(1.5))),
+        ));
+        objects.add(sphere(
+            Point::new(0.0, 3.0, 1.0),
+            0.5,
+            Material::new(MaterialKind::DiffuseLight(DiffuseLight::from_emission(Color::new(4.0, 4.0, 4.0)))),
+        ));
+        Hittable::List(objects)
+    }
+
+    fn render(camera: &Camera, world: &Hittable) -> Framebuffer {
+        let lights = Lights::new(world.lights(), vec![]);
+        let mut framebuffer = Framebuffer::new(camera.image.width, camera.image.height());
+        camera.render_pass(&mut framebuffer, &mut [], 4, world, &lights, None);
+        framebuffer
+    }
+
+    fn camera(seed: u64, tile_width: u32, tile_height: u32) -> Camera {
+        let mut camera = Camera::builder(config())
+            .image(Image::new(23, 1.5))
+            .samples_per_pixel(4)
+            .max_depth(8)
+            .look_from(Point::new(0.0, 1.0, 4.0))
+            .look_at(Point::new(0.0, 0.5, 0.0))
+            .seed(seed)
+            .build();
+        camera.tile_width = tile_width;
+        camera.tile_height = tile_height;
+        camera
+    }
+
+    fn same_pixels(a: &Framebuffer, b: &Framebuffer) -> bool {
+        a.pixels().iter().zip(b.pixels()).all(|(a, b)| {
+            a.red_component().to_bits() == b.red_component().to_bits()
+                && a.green_component().to_bits() == b.green_component().to_bits()
+                && a.blue_component().to_bits() == b.blue_component().to_bits()
+        })
+    }
+
+    #[test]
+    fn test_same_seed_same_pixels_for_any_tile_size() {
+        let world = world();
+        let expected = render(&camera(7, 64, 64), &world);
+
+        for (tile_width, tile_height) in [(1, 1), (5, 3), (16, 16)] {
+            let framebuffer = render(&camera(7, tile_width, tile_height), &world);
+            assert!(same_pixels(&expected, &framebuffer));
+        }
+
+        assert!(!same_pixels(&expected, &render(&camera(8, 64, 64), &world)));
+    }
 }
@@ -1,4 +1,3 @@
-use crate::core::math;
 use crate::core::math::interval::Interval;
 use crate::core::math::macros::impl_vec_like_conversion;
 use crate::core::math::point::PointKind;
@@ -47,27 +46,61 @@ impl Color {
         Color::new(0.0, 1.0, 1.0)
     }
 
-    pub(crate) fn to_bytes_string(&self) -> String {
+    /// Whether no light gets through, in any channel
+    pub(crate) fn is_black(&self) -> bool {
+        self.x <= 0.0 && self.y <= 0.0 && self.z <= 0.0
+    }
+
+    /// The relative luminance of a linear color (Rec. 709 weights)
+    pub(crate) fn luminance(&self) -> Real {
+        0.2126 * self.red_component()
+            + 0.7152 * self.green_component()
+            + 0.0722 * self.blue_component()
+    }
+
+    /// Encodes each component with the sRGB transfer curve and quantizes it into a byte.
+    pub(crate) fn to_bytes(&self) -> [u8; 3] {
         let intensity = Interval::new(0.0, 0.999);
 
-        let to_byte = |component: Real| -> u16 {
-            let component = Color::linear_to_gamma(component);
-            (intensity.clamp(component) * 256.0) as u16
+        let to_byte = |component: Real| -> u8 {
+            let component = Color::linear_to_srgb(component);
+            (intensity.clamp(component) * 256.0) as u8
         };
 
-        format!(
-            "{} {} {}",
+        [
             to_byte(self.red_component()),
             to_byte(self.green_component()),
-            to_byte(self.blue_component())
-        )
+            to_byte(self.blue_component()),
+        ]
     }
 
-    fn linear_to_gamma(linear_component: Real) -> Real {
-        if linear_component > 0.0 {
-            linear_component.powf(1.0 / math::GAMMA as f64)
-        } else {
+    /// The linear components, as expected by floating-point image formats.
+    pub(crate) fn to_f32_array(&self) -> [f32; 3] {
+        [
+            self.red_component() as f32,
+            self.green_component() as f32,
+            self.blue_component() as f32,
+        ]
+    }
+
+    /// The sRGB transfer function (the "gamma" encoding), which is linear near black
+    /// and approximately a 2.4 power curve everywhere else.
+    pub(crate) fn linear_to_srgb(linear_component: Real) -> Real {
+        if linear_component <= 0.0 {
             0.0
+        } else if linear_component <= 0.0031308 {
+            12.92 * linear_component
+        } else {
+            1.055 * linear_component.powf(1.0 / 2.4) - 0.055
+        }
+    }
+
+    /// The inverse of [`Self::linear_to_srgb`]
+    pub(crate) fn srgb_to_linear(srgb_component: Real) -> Real {
+        if srgb_component <= 0.04045 {
+            srgb_component / 12.92
+        } else {
+            ((srgb_component + 0.055) / 1.055).powf(2.4)
         }
     }
 }
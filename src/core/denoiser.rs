@@ -0,0 +1,266 @@
+use crate::core::framebuffer::Framebuffer;
+use crate::core::math::Real;
+use crate::core::Color;
+use rayon::prelude::*;
+
+/// An edge-avoiding à-trous wavelet filter (Dammertz et al.), guided by the albedo and the
+/// normals of the first hits. Each iteration blurs with a 5x5 kernel whose taps are twice as far
+/// apart as in the previous one, and the taps that differ too much from the center in normal,
+/// albedo or luminance get little weight, so the edges and the textures stay sharp. As in SVGF,
+/// how much the luminance may differ depends on the variance of the pixels, so the noisy parts of
+/// the image get blurred more than the converged ones.
+#[derive(Clone, Debug)]
+pub(crate) struct Denoiser {
+    iterations: u32,
+
+    /// How many standard deviations the luminance of a tap may be from the center's
+    luminance_sigma: Real,
+    normal_sigma: Real,
+    albedo_sigma: Real,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            luminance_sigma: 4.0,
+            normal_sigma: 0.3,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+/// The B3 spline, the 1D kernel of the filter
+const KERNEL: [Real; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// The 1D kernel of the blur of the variances
+const BLUR: [Real; 3] = [1.0 / 4.0, 1.0 / 2.0, 1.0 / 4.0];
+
+/// Albedo components below this aren't divided out of the color, which would amplify the noise
+const MIN_ALBEDO: Real = 0.01;
+
+impl Denoiser {
+    /// Denoises `image` using the `albedo` and `normal` passes of the same render. The pixels
+    /// without samples stay that way. Returns `None` if the passes are missing some of the
+    /// pixels of the image, which happens when a render is resumed without them.
+    pub(crate) fn denoise(
+        &self,
+        image: &Framebuffer,
+        albedo: &Framebuffer,
+        normal: &Framebuffer,
+    ) -> Option<Framebuffer> {
+        let (width, height) = (image.width(), image.height());
+
+        // after an interruption, the pixels without samples mustn't darken their neighbors
+        let sampled: Vec<bool> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| image.sample_count(x, y) > 0)
+            .collect();
+
+        // without its guides, a pixel would be blurred with anything that is black and faces away
+        let guided = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .zip(&sampled)
+            .all(|((x, y), &sampled)| {
+                !sampled || (albedo.sample_count(x, y) > 0 && normal.sample_count(x, y) > 0)
+            });
+        if !guided {
+            return None;
+        }
+        let albedo = albedo.pixels();
+        let normal = normal.pixels();
+
+        // the texture detail is in the albedo, so only the lighting gets filtered
+        let demodulation: Vec<Color> = albedo
+            .iter()
+            .map(|albedo| {
+                let component = |value: Real| if value > MIN_ALBEDO { value } else { 1.0 };
+                Color::new(
+                    component(albedo.red_component()),
+                    component(albedo.green_component()),
+                    component(albedo.blue_component()),
+                )
+            })
+            .collect();
+        let mut lighting: Vec<Color> = image
+            .pixels()
+            .iter()
+            .zip(&demodulation)
+            .map(|(color, demodulation)| {
+                Color::new(
+                    color.x / demodulation.x,
+                    color.y / demodulation.y,
+                    color.z / demodulation.z,
+                )
+            })
+            .collect();
+
+        // the variance of each mean, which tells how much of the differences between the pixels
+        // is noise. It needs two samples, so the pixels with one are assumed to be all noise.
+        let mut variances: Vec<Real> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .enumerate()
+            .map(|(index, (x, y))| match image.standard_error(x, y) {
+                Some(standard_error) => {
+                    let standard_error = standard_error / demodulation[index].luminance();
+                    standard_error * standard_error
+                }
+                None => lighting[index].luminance() * lighting[index].luminance(),
+            })
+            .collect();
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+
+            // a pixel whose few samples happen to agree would otherwise keep all of its noise
+            let blurred_variances: Vec<Real> = (0..height)
+                .into_par_iter()
+                .flat_map_iter(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let mut sum = 0.0;
+                    let mut weight_sum = 0.0;
+                    for (j, kernel_y) in BLUR.iter().enumerate() {
+                        for (i, kernel_x) in BLUR.iter().enumerate() {
+                            let offset = (i as i64 - 1, j as i64 - 1);
+                            let tap = tap_index(x, y, offset, width, height);
+                            if let Some(tap) = tap.filter(|&tap| sampled[tap]) {
+                                sum += kernel_x * kernel_y * variances[tap];
+                                weight_sum += kernel_x * kernel_y;
+                            }
+                        }
+                    }
+                    if weight_sum > 0.0 {
+                        sum / weight_sum
+                    } else {
+                        0.0
+                    }
+                })
+                .collect();
+
+            let filtered: Vec<(Color, Real)> = (0..height)
+                .into_par_iter()
+                .flat_map_iter(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let center = (y * width + x) as usize;
+                    let luminance = lighting[center].luminance();
+                    let luminance_scale =
+                        self.luminance_sigma * blurred_variances[center].sqrt() + Real::EPSILON;
+
+                    let mut sum = Color::black();
+                    let mut variance_sum = 0.0;
+                    let mut weight_sum = 0.0;
+                    for (j, kernel_y) in KERNEL.iter().enumerate() {
+                        for (i, kernel_x) in KERNEL.iter().enumerate() {
+                            let offset = ((i as i64 - 2) * step, (j as i64 - 2) * step);
+                            let tap = tap_index(x, y, offset, width, height);
+                            let Some(tap) = tap.filter(|&tap| sampled[tap]) else {
+                                continue;
+                            };
+
+                            let distance = (luminance - lighting[tap].luminance()).abs()
+                                / luminance_scale
+                                + distance_squared(&normal[center], &normal[tap])
+                                    / (self.normal_sigma * self.normal_sigma)
+                                + distance_squared(&albedo[center], &albedo[tap])
+                                    / (self.albedo_sigma * self.albedo_sigma);
+                            let weight = kernel_x * kernel_y * (-distance).exp();
+
+                            sum = sum + &lighting[tap] * weight;
+                            variance_sum += weight * weight * variances[tap];
+                            weight_sum += weight;
+                        }
+                    }
+
+                    if weight_sum > 0.0 {
+                        (sum / weight_sum, variance_sum / (weight_sum * weight_sum))
+                    } else {
+                        (Color::black(), 0.0)
+                    }
+                })
+                .collect();
+            (lighting, variances) = filtered.into_iter().unzip();
+        }
+
+        let mut denoised = Framebuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let index = (y * width + x) as usize;
+                if sampled[index] {
+                    denoised.add_sample(x, y, &(&lighting[index] * demodulation[index].clone()));
+                }
+            }
+        }
+        Some(denoised)
+    }
+}
+
+/// The index of the pixel at `offset` from `x`, `y`, if it is in the image
+fn tap_index(x: u32, y: u32, offset: (i64, i64), width: u32, height: u32) -> Option<usize> {
+    let tap_x = x as i64 + offset.0;
+    let tap_y = y as i64 + offset.1;
+    if tap_x < 0 || tap_y < 0 || tap_x >= width as i64 || tap_y >= height as i64 {
+        None
+    } else {
+        Some((tap_y * width as i64 + tap_x) as usize)
+    }
+}
+
+fn distance_squared(a: &Color, b: &Color) -> Real {
+    let (x, y, z) = (a.x - b.x, a.y - b.y, a.z - b.z);
+    x * x + y * y + z * z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Denoiser;
+    use crate::core::framebuffer::Framebuffer;
+    use crate::core::Color;
+
+    fn filled(width: u32, height: u32, color: &Color) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                framebuffer.add_sample(x, y, color);
+            }
+        }
+        framebuffer
+    }
+
+    #[test]
+    fn test_denoise_uniform_image() {
+        let color = Color::new(0.5, 0.25, 0.125);
+        let image = filled(8, 8, &color);
+        let albedo = filled(8, 8, &Color::new(0.8, 0.8, 0.8));
+        let normal = filled(8, 8, &Color::new(0.0, 0.0, 1.0));
+
+        let denoised = Denoiser::default()
+            .denoise(&image, &albedo, &normal)
+            .unwrap();
+        for pixel in denoised.pixels() {
+            assert!((pixel.red_component() - color.red_component()).abs() < 1e-6);
+            assert!((pixel.green_component() - color.green_component()).abs() < 1e-6);
+            assert!((pixel.blue_component() - color.blue_component()).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_denoise_without_guides() {
+        let image = filled(8, 8, &Color::new(0.5, 0.5, 0.5));
+        let normal = filled(8, 8, &Color::new(0.0, 0.0, 1.0));
+        let denoiser = Denoiser::default();
+
+        // the guides of a resumed render that lost them
+        let albedo = Framebuffer::new(8, 8);
+        assert!(denoiser.denoise(&image, &albedo, &normal).is_none());
+
+        // the guides only need the pixels that have samples
+        let mut image = Framebuffer::new(8, 8);
+        image.add_sample(2, 3, &Color::new(0.5, 0.5, 0.5));
+        let mut albedo = Framebuffer::new(8, 8);
+        albedo.add_sample(2, 3, &Color::new(0.8, 0.8, 0.8));
+        assert!(denoiser.denoise(&image, &albedo, &normal).is_some());
+        albedo = Framebuffer::new(8, 8);
+        albedo.add_sample(3, 3, &Color::new(0.8, 0.8, 0.8));
+        assert!(denoiser.denoise(&image, &albedo, &normal).is_none());
+    }
+}
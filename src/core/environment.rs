@@ -0,0 +1,317 @@
+use crate::core::Color;
+use crate::core::math::{self, Real, Vec3D};
+use image::{DynamicImage, ImageReader, ImageResult};
+use std::path::Path;
+
+/// The light coming from every direction, read from an equirectangular image: the longitude goes
+/// along its width, with -z in the middle, and the latitude along its height, with +y at the top.
+/// The brighter a direction, the more often the lights sample it.
+#[derive(Debug)]
+pub(crate) struct Environment {
+    width: u32,
+    height: u32,
+
+    /// The linear colors, scaled by the intensity
+    pixels: Vec<Color>,
+
+    /// Around the vertical axis, in radians
+    rotation: Real,
+
+    /// Proportional to the light that each pixel sends towards the scene
+    distribution: Distribution2D,
+}
+
+impl Environment {
+    /// Loads an environment map. Floating-point images (HDR, EXR) are read as linear, the others
+    /// as sRGB. `rotation` turns the map around the vertical axis, in degrees.
+    pub(crate) fn from_path(path: &Path, rotation: Real, intensity: Real) -> ImageResult<Self> {
+        let image = ImageReader::open(path)?.decode()?;
+        let linear = matches!(
+            image,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        );
+
+        let image = image.to_rgb32f();
+        let component = |value: f32| {
+            let value = value as Real;
+            if linear {
+                value
+            } else {
+                Color::srgb_to_linear(value)
+            }
+        };
+        let pixels = image
+            .pixels()
+            .map(|pixel| {
+                Color::new(
+                    component(pixel[0]),
+                    component(pixel[1]),
+                    component(pixel[2]),
+                ) * intensity
+            })
+            .collect();
+
+        Ok(Self::new(
+            image.width(),
+            image.height(),
+            pixels,
+            math::degrees_to_radians(rotation),
+        ))
+    }
+
+    /// Tabulates `radiance` over every direction, in `width` by `height` pixels, so the
+    /// directions can be sampled in proportion to it.
+    pub(crate) fn from_radiance(width: u32, height: u32, radiance: impl Fn(&Vec3D) -> Color) -> Self {
+        let pixels = (0..height)
+            .flat_map(|j| (0..width).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                radiance(&uv_to_direction(
+                    (i as Real + 0.5) / width as Real,
+                    (j as Real + 0.5) / height as Real,
+                ))
+            })
+            .collect();
+        Self::new(width, height, pixels, 0.0)
+    }
+
+    fn new(width: u32, height: u32, pixels: Vec<Color>, rotation: Real) -> Self {
+        // the rows near the poles cover less of the sphere
+        let rows = (0..height as usize)
+            .map(|j| {
+                let sin_theta = (math::PI * (j as Real + 0.5) / height as Real).sin();
+                pixels[j * width as usize..(j + 1) * width as usize]
+                    .iter()
+                    .map(|pixel| pixel.luminance().max(0.0) * sin_theta)
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+            rotation,
+            distribution: Distribution2D::new(rows),
+        }
+    }
+
+    /// The light coming from `direction`
+    pub(crate) fn value(&self, direction: &Vec3D) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let i = ((u * self.width as Real) as u32).min(self.width - 1);
+        let j = ((v * self.height as Real) as u32).min(self.height - 1);
+        self.pixels[(j * self.width + i) as usize].clone()
+    }
+
+    /// The density of [`Self::random_direction`], over solid angle
+    pub(crate) fn pdf_value(&self, direction: &Vec3D) -> Real {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (math::PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        // the image covers 2π by π radians
+        self.distribution.pdf(u, v) / (2.0 * math::PI * math::PI * sin_theta)
+    }
+
+    /// A direction chosen in proportion to the light coming from it
+    pub(crate) fn random_direction(&self) -> Vec3D {
+        let (u, v) = math::random_pair();
+        let (u, v) = self.distribution.sample(u, v);
+        rotate_y(&uv_to_direction(u, v), self.rotation)
+    }
+
+    fn direction_to_uv(&self, direction: &Vec3D) -> (Real, Real) {
+        let direction = rotate_y(&direction.to_unit().0, -self.rotation);
+        let phi = direction.x.atan2(-direction.z);
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        (0.5 + phi / (2.0 * math::PI), theta / math::PI)
+    }
+}
+
+fn uv_to_direction(u: Real, v: Real) -> Vec3D {
+    let phi = (u - 0.5) * 2.0 * math::PI;
+    let theta = v * math::PI;
+    Vec3D::new(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
+
+fn rotate_y(direction: &Vec3D, angle: Real) -> Vec3D {
+    let (sin, cos) = angle.sin_cos();
+    Vec3D::new(
+        cos * direction.x + sin * direction.z,
+        direction.y,
+        -sin * direction.x + cos * direction.z,
+    )
+}
+
+/// A piecewise-constant density over [0, 1), with as many equal steps as its function has
+/// values.
+#[derive(Debug)]
+struct Distribution1D {
+    function: Vec<Real>,
+
+    /// The cumulative distribution at the start of every step, and 1 at the end
+    cdf: Vec<Real>,
+
+    /// The integral of the function over [0, 1). When it is zero, the steps are sampled
+    /// uniformly.
+    integral: Real,
+}
+
+impl Distribution1D {
+    fn new(function: Vec<Real>) -> Self {
+        let count = function.len();
+
+        let mut cdf = Vec::with_capacity(count + 1);
+        cdf.push(0.0);
+        for value in &function {
+            cdf.push(cdf[cdf.len() - 1] + value / count as Real);
+        }
+        let integral = cdf[count];
+        if integral > 0.0 {
+            cdf.iter_mut().for_each(|value| *value /= integral);
+        } else {
+            // nothing to favor, so every step is as likely
+            cdf = (0..=count)
+                .map(|step| step as Real / count as Real)
+                .collect();
+        }
+
+        Self {
+            function,
+            cdf,
+            integral,
+        }
+    }
+
+    /// Maps a uniform number in [0, 1) to a value distributed like the function, together with
+    /// the step it is in.
+    fn sample(&self, u: Real) -> (Real, usize) {
+        let count = self.function.len();
+        let step = self.cdf.partition_point(|&value| value <= u).clamp(1, count) - 1;
+        let (start, end) = (self.cdf[step], self.cdf[step + 1]);
+        let within_step = if end > start {
+            (u - start) / (end - start)
+        } else {
+            0.0
+        };
+        (
+            ((step as Real + within_step) / count as Real).min(1.0 - Real::EPSILON),
+            step,
+        )
+    }
+
+    fn pdf(&self, step: usize) -> Real {
+        if self.integral > 0.0 {
+            self.function[step] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// A piecewise-constant density over [0, 1)², sampled row first, then within the row
+#[derive(Debug)]
+struct Distribution2D {
+    /// The distribution within each row
+    rows: Vec<Distribution1D>,
+
+    /// The distribution of the rows
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    fn new(rows: Vec<Vec<Real>>) -> Self {
+        let rows: Vec<Distribution1D> = rows.into_iter().map(Distribution1D::new).collect();
+
+        // the black rows are never picked, however their own steps are sampled
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral).collect());
+        Self { rows, marginal }
+    }
+
+    fn sample(&self, u: Real, v: Real) -> (Real, Real) {
+        let (y, row) = self.marginal.sample(v);
+        let (x, _) = self.rows[row].sample(u);
+        (x, y)
+    }
+
+    fn pdf(&self, x: Real, y: Real) -> Real {
+        let row = ((y * self.rows.len() as Real) as usize).min(self.rows.len() - 1);
+        let row_distribution = &self.rows[row];
+        let column = ((x * row_distribution.function.len() as Real) as usize)
+            .min(row_distribution.function.len() - 1);
+        self.marginal.pdf(row) * row_distribution.pdf(column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Environment;
+    use crate::core::Color;
+    use crate::core::math::{self, Real, Vec3D};
+
+    /// The integral of the density of the directions over the sphere
+    fn pdf_integral(environment: &Environment) -> Real {
+        let (thetas, phis) = (320, 640);
+        let d_theta = math::PI / thetas as Real;
+        let d_phi = 2.0 * math::PI / phis as Real;
+
+        let mut integral = 0.0;
+        for i in 0..thetas {
+            let theta = (i as Real + 0.5) * d_theta;
+            let (sin_theta, cos_theta) = theta.sin_cos();
+            for j in 0..phis {
+                let phi = (j as Real + 0.5) * d_phi;
+                let direction = Vec3D::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+                integral += environment.pdf_value(&direction) * sin_theta * d_theta * d_phi;
+            }
+        }
+        integral
+    }
+
+    #[test]
+    fn test_pdf_integrates_to_one() {
+        let environments = [
+            Environment::from_radiance(32, 16, |_| Color::from_scalar(1.0)),
+            Environment::from_radiance(32, 16, |direction| {
+                Color::from_scalar(1.0 + direction.x.max(0.0) * 10.0)
+            }),
+            // the top half is black, so its rows have nothing to sample
+            Environment::from_radiance(32, 16, |direction| {
+                if direction.y > 0.0 {
+                    Color::black()
+                } else {
+                    Color::from_scalar(1.0 - direction.y)
+                }
+            }),
+            Environment::from_radiance(32, 16, |_| Color::black()),
+        ];
+        for environment in &environments {
+            let integral = pdf_integral(environment);
+            assert!((integral - 1.0).abs() < 1e-3, "{integral}");
+        }
+    }
+
+    #[test]
+    fn test_black_rows_are_never_sampled() {
+        let environment = Environment::from_radiance(32, 16, |direction| {
+            if direction.y > 0.0 {
+                Color::black()
+            } else {
+                Color::from_scalar(1.0)
+            }
+        });
+
+        assert_eq!(environment.pdf_value(&Vec3D::new(0.3, 0.8, -0.2)), 0.0);
+        for _ in 0..1000 {
+            let direction = environment.random_direction();
+            assert!(direction.y <= 0.0, "{direction:?}");
+            assert!(environment.pdf_value(&direction) > 0.0);
+        }
+    }
+}
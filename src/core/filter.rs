@@ -0,0 +1,257 @@
+use crate::core::math::{self, Real};
+use serde::Deserialize;
+
+/// Reconstruction filters, which decide how much each sample contributes to its pixel depending
+/// on its distance from the center. All of them are separable: the weight of an offset is the
+/// product of the weights of its horizontal and vertical components.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Filter {
+    /// Every sample within the radius counts the same
+    #[default]
+    Box,
+
+    /// The weight falls off linearly to 0 at the radius
+    Tent,
+
+    /// A Gaussian with a standard deviation of a third of the radius, shifted down so it reaches
+    /// 0 at the radius
+    Gaussian,
+
+    /// The Mitchell-Netravali cubic with B = C = 1/3. Its negative lobes sharpen the edges a bit.
+    Mitchell,
+
+    /// A sinc windowed by a wider sinc, with as many lobes as the radius. The sharpest of them,
+    /// but prone to ringing around bright edges.
+    Lanczos,
+}
+
+impl Filter {
+    /// The radius, in pixels, the filter is designed for
+    pub(crate) fn default_radius(&self) -> Real {
+        match self {
+            Self::Box => 0.5,
+            Self::Tent => 1.0,
+            Self::Gaussian => 1.5,
+            Self::Mitchell => 2.0,
+            Self::Lanczos => 3.0,
+        }
+    }
+
+    /// The weight of the offset `x` in a filter of radius `radius`, before normalization
+    fn evaluate(&self, x: Real, radius: Real) -> Real {
+        let x = x.abs();
+        if x > radius {
+            return 0.0;
+        }
+
+        match self {
+            Self::Box => 1.0,
+            Self::Tent => radius - x,
+            Self::Gaussian => {
+                let sigma = radius / 3.0;
+                let gaussian = |x: Real| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Self::Mitchell => {
+                // the cubic is defined over [-2, 2]
+                let x = 2.0 * x / radius;
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+            }
+            Self::Lanczos => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+fn sinc(x: Real) -> Real {
+    if x < 1e-5 {
+        1.0
+    } else {
+        (math::PI * x).sin() / (math::PI * x)
+    }
+}
+
+/// Draws pixel offsets distributed like a [`Filter`] (filter importance sampling), so the
+/// samples can simply be averaged. The parts where the filter is negative are sampled like the
+/// positive ones, and their samples get a negative weight instead.
+#[derive(Clone, Debug)]
+pub(crate) struct FilterSampler {
+    filter: Filter,
+    radius: Real,
+
+    /// The cumulative distribution of the absolute filter over [-radius, radius], tabulated
+    /// in [`Self::RESOLUTION`] equal steps. Empty for the box filter, which is sampled directly.
+    cdf: Vec<Real>,
+
+    /// The sign of the filter in each step
+    signs: Vec<Real>,
+
+    /// The ratio between the integral of the absolute filter and the integral of the filter,
+    /// which keeps the weights unbiased along each axis
+    scale: Real,
+}
+
+impl FilterSampler {
+    const RESOLUTION: usize = 256;
+
+    /// Narrower filters can't be tabulated reliably. Turning off the antialiasing is the way to
+    /// sample the centers of the pixels.
+    const MIN_RADIUS: Real = 0.01;
+
+    pub(crate) fn new(filter: Filter, radius: Real) -> Self {
+        // also catches NaN
+        let radius = if radius >= Self::MIN_RADIUS {
+            radius
+        } else {
+            log::warn!(
+                "The filter radius must be at least {}, but it is {radius}. Using {} instead.",
+                Self::MIN_RADIUS,
+                Self::MIN_RADIUS
+            );
+            Self::MIN_RADIUS
+        };
+
+        if filter == Filter::Box {
+            return Self {
+                filter,
+                radius,
+                cdf: vec![],
+                signs: vec![],
+                scale: 1.0,
+            };
+        }
+
+        let step = 2.0 * radius / Self::RESOLUTION as Real;
+        let values: Vec<Real> = (0..Self::RESOLUTION)
+            .map(|i| filter.evaluate(-radius + (i as Real + 0.5) * step, radius))
+            .collect();
+
+        let mut cdf = Vec::with_capacity(Self::RESOLUTION + 1);
+        cdf.push(0.0);
+        for value in &values {
+            cdf.push(cdf[cdf.len() - 1] + value.abs());
+        }
+        let absolute_integral = cdf[Self::RESOLUTION];
+        let integral: Real = values.iter().sum();
+        cdf.iter_mut().for_each(|value| *value /= absolute_integral);
+
+        Self {
+            filter,
+            radius,
+            cdf,
+            signs: values.iter().map(|value| value.signum()).collect(),
+            scale: absolute_integral / integral,
+        }
+    }
+
+    pub(crate) fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    pub(crate) fn radius(&self) -> Real {
+        self.radius
+    }
+
+    /// Maps two uniform numbers in [0, 1) to an offset from the center of the pixel, and
+    /// returns it with the weight of the sample.
+    pub(crate) fn sample(&self, u: Real, v: Real) -> (Real, Real, Real) {
+        let (x, x_weight) = self.sample_1d(u);
+        let (y, y_weight) = self.sample_1d(v);
+        (x, y, x_weight * y_weight)
+    }
+
+    fn sample_1d(&self, u: Real) -> (Real, Real) {
+        if self.cdf.is_empty() {
+            return (self.radius * (2.0 * u - 1.0), 1.0);
+        }
+
+        // the last step whose cumulative value is not above u
+        let step = self
+            .cdf
+            .partition_point(|&value| value <= u)
+            .clamp(1, Self::RESOLUTION)
+            - 1;
+        let (start, end) = (self.cdf[step], self.cdf[step + 1]);
+        let within_step = if end > start {
+            (u - start) / (end - start)
+        } else {
+            0.5
+        };
+
+        let step_width = 2.0 * self.radius / Self::RESOLUTION as Real;
+        let x = -self.radius + (step as Real + within_step) * step_width;
+        (x, self.signs[step] * self.scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, FilterSampler};
+    use crate::core::math::Real;
+
+    const FILTERS: [Filter; 5] = [
+        Filter::Box,
+        Filter::Tent,
+        Filter::Gaussian,
+        Filter::Mitchell,
+        Filter::Lanczos,
+    ];
+
+    /// The average of the weights of `count` stratified samples along one axis
+    fn average_weight(sampler: &FilterSampler, count: usize) -> Real {
+        let total: Real = (0..count)
+            .map(|i| sampler.sample_1d((i as Real + 0.5) / count as Real).1)
+            .sum();
+        total / count as Real
+    }
+
+    #[test]
+    fn test_weights_average_to_one() {
+        for filter in FILTERS {
+            for radius in [filter.default_radius(), 0.75, 2.5] {
+                let sampler = FilterSampler::new(filter, radius);
+                let average = average_weight(&sampler, 100_000);
+                assert!(
+                    (average - 1.0).abs() < 1e-3,
+                    "{filter:?} with a radius of {radius}: {average}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_offsets_within_radius() {
+        for filter in FILTERS {
+            let sampler = FilterSampler::new(filter, filter.default_radius());
+            for i in 0..1000 {
+                let (x, _) = sampler.sample_1d(i as Real / 1000.0);
+                assert!(x.abs() <= filter.default_radius());
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_radius() {
+        for radius in [0.0, -1.0, Real::NAN] {
+            for filter in FILTERS {
+                let sampler = FilterSampler::new(filter, radius);
+                assert_eq!(sampler.radius(), FilterSampler::MIN_RADIUS);
+
+                let (x, y, weight) = sampler.sample(0.3, 0.8);
+                assert!(x.is_finite() && y.is_finite() && weight.is_finite());
+            }
+        }
+    }
+}
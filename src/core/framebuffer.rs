@@ -0,0 +1,306 @@
+use crate::core::Color;
+use crate::core::math::Real;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// A buffer of linear colors. Instead of storing the final pixel values, each pixel keeps the
+/// sum of all its samples together with the number of samples taken, so buffers from partial
+/// renders can be merged and refined before anything gets quantized.
+#[derive(Clone, Debug)]
+pub(crate) struct Framebuffer {
+    width: u32,
+    height: u32,
+    sums: Vec<Color>,
+
+    /// The sums of the squared luminances of the samples, for estimating the variance
+    squared_luminance_sums: Vec<Real>,
+
+    sample_counts: Vec<u32>,
+}
+
+impl Framebuffer {
+    pub(crate) fn new(width: u32, height: u32) -> Self {
+        let size = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            sums: vec![Color::black(); size],
+            squared_luminance_sums: vec![0.0; size],
+            sample_counts: vec![0; size],
+        }
+    }
+
+    pub(crate) fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn index(&self, i: u32, j: u32) -> usize {
+        j as usize * self.width as usize + i as usize
+    }
+
+    pub(crate) fn add_sample(&mut self, i: u32, j: u32, sample: &Color) {
+        let luminance = sample.luminance();
+        self.add_samples(i, j, sample, luminance * luminance, 1);
+    }
+
+    /// Accumulates `count` samples whose colors add up to `sum`, and whose squared luminances
+    /// add up to `squared_luminance_sum`, into the pixel at `i`, `j`.
+    fn add_samples(
+        &mut self,
+        i: u32,
+        j: u32,
+        sum: &Color,
+        squared_luminance_sum: Real,
+        count: u32,
+    ) {
+        let index = self.index(i, j);
+        self.sums[index] = &self.sums[index] + sum;
+        self.squared_luminance_sums[index] += squared_luminance_sum;
+        self.sample_counts[index] += count;
+    }
+
+    pub(crate) fn sample_count(&self, i: u32, j: u32) -> u32 {
+        self.sample_counts[self.index(i, j)]
+    }
+
+    /// The fewest samples taken by any pixel
+    pub(crate) fn min_sample_count(&self) -> u32 {
+        self.sample_counts.iter().copied().min().unwrap_or(0)
+    }
+
+    /// The most samples taken by any pixel
+    pub(crate) fn max_sample_count(&self) -> u32 {
+        self.sample_counts.iter().copied().max().unwrap_or(0)
+    }
+
+    /// An estimate of how far the luminance of the pixel at `i`, `j` still is from converging:
+    /// the standard error of its mean, relative to the mean. Needs at least two samples.
+    pub(crate) fn relative_error(&self, i: u32, j: u32) -> Option<Real> {
+        let standard_error = self.standard_error(i, j)?;
+        if standard_error == 0.0 {
+            Some(0.0)
+        } else {
+            let mean = self.pixel(i, j).luminance();
+            Some(standard_error / mean.max(Real::EPSILON))
+        }
+    }
+
+    /// The standard error of the mean luminance of the pixel at `i`, `j`: how far it can be
+    /// expected to be from the converged value. Needs at least two samples.
+    pub(crate) fn standard_error(&self, i: u32, j: u32) -> Option<Real> {
+        let index = self.index(i, j);
+        let count = self.sample_counts[index];
+        if count < 2 {
+            return None;
+        }
+
+        let n = count as Real;
+        let mean = self.sums[index].luminance() / n;
+        let variance =
+            ((self.squared_luminance_sums[index] - n * mean * mean) / (n - 1.0)).max(0.0);
+        Some((variance / n).sqrt())
+    }
+
+    /// The average of all the samples taken for the pixel at `i`, `j`.
+    /// Pixels without samples are black.
+    pub(crate) fn pixel(&self, i: u32, j: u32) -> Color {
+        let index = self.index(i, j);
+        match self.sample_counts[index] {
+            0 => Color::black(),
+            count => &self.sums[index] / count as Real,
+        }
+    }
+
+    /// The averaged pixels in row-major order.
+    pub(crate) fn pixels(&self) -> Vec<Color> {
+        (0..self.height)
+            .flat_map(|j| (0..self.width).map(move |i| (i, j)))
+            .map(|(i, j)| self.pixel(i, j))
+            .collect()
+    }
+
+    /// Accumulates the samples of `other` into this buffer, with the upper-left pixel
+    /// of `other` placed at `x`, `y`. Pixels that fall outside this buffer are ignored.
+    pub(crate) fn merge_at(&mut self, x: u32, y: u32, other: &Framebuffer) {
+        for j in 0..other.height.min(self.height.saturating_sub(y)) {
+            for i in 0..other.width.min(self.width.saturating_sub(x)) {
+                let index = other.index(i, j);
+                self.add_samples(
+                    x + i,
+                    y + j,
+                    &other.sums[index],
+                    other.squared_luminance_sums[index],
+                    other.sample_counts[index],
+                );
+            }
+        }
+    }
+
+    const CHECKPOINT_MAGIC: &'static [u8; 8] = b"EANRAYFB";
+    const CHECKPOINT_VERSION: u32 = 2;
+
+    /// The magic number, the version, the width and the height
+    const CHECKPOINT_HEADER_SIZE: u64 = 8 + 3 * 4;
+
+    /// The sum of the colors and the sum of the squared luminances, followed by the sample count
+    const CHECKPOINT_PIXEL_SIZE: u64 = 4 * size_of::<Real>() as u64 + 4;
+
+    /// Writes the accumulated samples to `path`, so a render can be resumed later with
+    /// [`Self::load`]. The file is replaced only once it has been completely written.
+    pub(crate) fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temporary_path = path.as_os_str().to_owned();
+        temporary_path.push(".tmp");
+        let mut writer = BufWriter::new(File::create(&temporary_path)?);
+
+        writer.write_all(Self::CHECKPOINT_MAGIC)?;
+        for value in [Self::CHECKPOINT_VERSION, self.width, self.height] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        for index in 0..self.sums.len() {
+            let sum = &self.sums[index];
+            for component in [
+                sum.red_component(),
+                sum.green_component(),
+                sum.blue_component(),
+                self.squared_luminance_sums[index],
+            ] {
+                writer.write_all(&component.to_le_bytes())?;
+            }
+            writer.write_all(&self.sample_counts[index].to_le_bytes())?;
+        }
+        writer.into_inner()?.sync_all()?;
+
+        fs::rename(temporary_path, path)
+    }
+
+    /// Reads a buffer written by [`Self::save`].
+    pub(crate) fn load(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let invalid = |message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {message}", path.display()),
+            )
+        };
+
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != Self::CHECKPOINT_MAGIC {
+            return Err(invalid("not a checkpoint file"));
+        }
+
+        if read_u32(&mut reader)? != Self::CHECKPOINT_VERSION {
+            return Err(invalid("unsupported checkpoint version"));
+        }
+        let (width, height) = (read_u32(&mut reader)?, read_u32(&mut reader)?);
+
+        // check the size before allocating anything, so a corrupted header can't ask for
+        // more memory than there is
+        let expected_size = (width as u64)
+            .checked_mul(height as u64)
+            .and_then(|pixels| pixels.checked_mul(Self::CHECKPOINT_PIXEL_SIZE))
+            .and_then(|size| size.checked_add(Self::CHECKPOINT_HEADER_SIZE));
+        if expected_size != Some(file_size) {
+            return Err(invalid(&format!(
+                "the size of the file doesn't match a {width}x{height} image"
+            )));
+        }
+
+        let mut framebuffer = Self::new(width, height);
+        for index in 0..framebuffer.sums.len() {
+            let (r, g, b) = (
+                read_real(&mut reader)?,
+                read_real(&mut reader)?,
+                read_real(&mut reader)?,
+            );
+            framebuffer.sums[index] = Color::new(r, g, b);
+            framebuffer.squared_luminance_sums[index] = read_real(&mut reader)?;
+            framebuffer.sample_counts[index] = read_u32(&mut reader)?;
+        }
+        Ok(framebuffer)
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_real(reader: &mut impl Read) -> io::Result<Real> {
+    let mut bytes = [0u8; size_of::<Real>()];
+    reader.read_exact(&mut bytes)?;
+    Ok(Real::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Framebuffer;
+    use crate::core::Color;
+    use std::fs;
+    use std::io;
+
+    #[test]
+    fn test_save_and_load() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.add_sample(0, 0, &Color::new(0.25, 0.5, 1.0));
+        framebuffer.add_sample(0, 0, &Color::new(2.0, 0.0, 0.125));
+        framebuffer.add_sample(2, 1, &Color::new(0.1, 0.2, 0.3));
+
+        let path = std::env::temp_dir().join("eanray_test_save_and_load.ckpt");
+        framebuffer.save(&path).unwrap();
+        let loaded = Framebuffer::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.width(), loaded.height()), (3, 2));
+        let components = |color: &Color| {
+            [
+                color.red_component(),
+                color.green_component(),
+                color.blue_component(),
+            ]
+        };
+        assert!(
+            loaded
+                .sums
+                .iter()
+                .zip(&framebuffer.sums)
+                .all(|(a, b)| components(a) == components(b))
+        );
+        assert_eq!(
+            loaded.squared_luminance_sums,
+            framebuffer.squared_luminance_sums
+        );
+        assert_eq!(loaded.sample_counts, framebuffer.sample_counts);
+    }
+
+    #[test]
+    fn test_load_rejects_wrong_size() {
+        let path = std::env::temp_dir().join("eanray_test_load_rejects_wrong_size.ckpt");
+        Framebuffer::new(4, 4).save(&path).unwrap();
+
+        // a header that claims a huge image, followed by the pixels of a small one
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        bytes[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        let error = Framebuffer::load(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // a truncated file
+        Framebuffer::new(4, 4).save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        let error = Framebuffer::load(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,11 +1,13 @@
 use crate::core::aabb::AABB;
-use crate::core::bvh::BVH;
+use crate::core::bvh::{SplitStrategy, BVH};
 use crate::core::materials::Material;
 use crate::core::math;
 use crate::core::math::interval::Interval;
+use crate::core::math::onb::Onb;
 use crate::core::math::ray::Ray;
 use crate::core::math::vector::UnitVec3D;
 use crate::core::math::{Point, Real, Vec3D};
+use crate::core::shapes::mesh::Mesh;
 use crate::core::shapes::planars::Planar;
 use crate::core::shapes::plane::Plane;
 use crate::core::shapes::quadrics::Quadric;
@@ -24,6 +26,9 @@ pub(crate) struct HitRecord<'a> {
     front_face: bool,
     u: Real,
     v: Real,
+
+    /// Along the surface, towards where `u` grows (dp/du), if the shape defines it
+    tangent: Option<Vec3D>,
 }
 
 impl<'a> HitRecord<'a> {
@@ -44,6 +49,14 @@ impl<'a> HitRecord<'a> {
             front_face: front_face.0,
             u: u.0,
             v: v.0,
+            tangent: None,
+        }
+    }
+
+    pub(crate) fn with_tangent(self, tangent: Vec3D) -> Self {
+        Self {
+            tangent: Some(tangent),
+            ..self
         }
     }
 
@@ -84,6 +97,15 @@ impl<'a> HitRecord<'a> {
     pub(crate) fn v(&self) -> Real {
         self.v
     }
+
+    /// The basis around the normal that the anisotropic materials are oriented by. Its `u` axis
+    /// follows the tangent, when there is one.
+    pub(crate) fn shading_frame(&self) -> Onb {
+        match &self.tangent {
+            Some(tangent) => Onb::with_tangent(&self.normal.0, tangent),
+            None => Onb::new(&self.normal.0),
+        }
+    }
 }
 
 pub(crate) struct HitPoint(pub(crate) Point);
@@ -103,6 +125,7 @@ pub(crate) enum Hittable {
     ConstantMedium(ConstantMedium),
     Plane(Plane),
     Transform(Transform),
+    Mesh(Mesh),
 }
 
 impl Hittable {
@@ -121,6 +144,9 @@ impl Hittable {
                 {
                     hit_record.hit_point = hit_record.hit_point.transform(transform.forward());
                     hit_record.normal = hit_record.normal.transform(transform.normal()).to_unit();
+                    hit_record.tangent = hit_record
+                        .tangent
+                        .map(|tangent| tangent.transform(transform.forward()));
                     Some(hit_record)
                 } else {
                     None
@@ -142,6 +168,7 @@ impl Hittable {
             Self::Planar(planar) => planar.hit(transformed_ray, ray_t),
             Self::ConstantMedium(constant_medium) => constant_medium.hit(transformed_ray, ray_t),
             Self::Plane(plane) => plane.hit(transformed_ray, ray_t),
+            Self::Mesh(mesh) => mesh.hit(transformed_ray, ray_t),
             _ => None,
         }
     }
@@ -155,6 +182,7 @@ impl Hittable {
             Self::ConstantMedium(constant_medium) => constant_medium.bounding_box(),
             Self::Plane(plane) => &plane.fields.bounding_box,
             Self::Transform(transform) => transform.bounding_box(),
+            Self::Mesh(mesh) => mesh.bounding_box(),
         }
     }
 
@@ -168,6 +196,123 @@ impl Hittable {
             && bbox.z().max < math::INFINITY
     }
 
+    /// The probability density of [`Self::random_direction`] generating `direction` from `origin`.
+    /// Only the shapes that can be sampled (see [`Self::collect_lights`]) have a non-zero density.
+    pub(crate) fn pdf_value(&self, origin: &Point, direction: &Vec3D, time: Real) -> Real {
+        match self {
+            Self::Quadric(Quadric::Sphere(sphere)) => sphere.pdf_value(origin, direction, time),
+            Self::Planar(planar) => planar.pdf_value(origin, direction, time),
+            Self::List(list) => list.pdf_value(origin, direction, time),
+
+            // Note: this is exact for rigid transformations and uniform scaling, which keep
+            // the angles intact. Non-uniform scaling only approximates the density.
+            Self::Transform(transform) => transform.object.pdf_value(
+                &origin.transform(transform.inverse()),
+                &direction.transform(transform.inverse()),
+                time,
+            ),
+            _ => 0.0,
+        }
+    }
+
+    /// Returns a random direction from `origin` towards this object.
+    pub(crate) fn random_direction(&self, origin: &Point, time: Real) -> Vec3D {
+        match self {
+            Self::Quadric(Quadric::Sphere(sphere)) => sphere.random_direction(origin, time),
+            Self::Planar(planar) => planar.random_direction(origin),
+            Self::List(list) => list.random_direction(origin, time),
+            Self::Transform(transform) => transform
+                .object
+                .random_direction(&origin.transform(transform.inverse()), time)
+                .transform(transform.forward()),
+            _ => Vec3D::random_unit().0,
+        }
+    }
+
+    /// Collects the emissive objects that can be sampled directly into `lights`.
+    pub(crate) fn collect_lights(&self, lights: &mut HittableList) {
+        match self {
+            Self::Quadric(quadric @ Quadric::Sphere(_))
+                if quadric.fields().material().is_emissive() =>
+            {
+                lights.add(self.clone())
+            }
+            Self::Planar(planar) if planar.fields.material().is_emissive() => {
+                lights.add(self.clone())
+            }
+            Self::Mesh(mesh) => mesh
+                .emissive_triangles()
+                .for_each(|triangle| lights.add(Self::Planar(triangle))),
+            Self::List(list) => list
+                .objects()
+                .iter()
+                .for_each(|object| object.collect_lights(lights)),
+            Self::BVH(bvh) => bvh
+                .objects()
+                .iter()
+                .for_each(|object| object.collect_lights(lights)),
+            Self::Transform(transform) => {
+                let mut object_lights = HittableList::empty();
+                transform.object.collect_lights(&mut object_lights);
+
+                for light in object_lights.objects() {
+                    lights.add(Hittable::Transform(Transform::new(
+                        Arc::new(light.clone()),
+                        transform.forward().clone(),
+                    )));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Gathers the materials of every primitive, in a stable order. A primitive shows up once
+    /// for every instance of it. Primitives share their materials, so the IDs of the materials
+    /// are keyed by the shared material, while each primitive holds its own handle to it.
+    pub(crate) fn collect_materials<'a>(&'a self, materials: &mut Vec<Vec<&'a Material>>) {
+        match self {
+            Self::Quadric(quadric) => materials.push(quadric.materials()),
+            Self::Planar(planar) => materials.push(vec![planar.fields.material()]),
+            Self::Plane(plane) => materials.push(vec![plane.fields.material()]),
+            Self::ConstantMedium(medium) => materials.push(vec![medium.phase_function()]),
+            Self::Mesh(mesh) => materials.push(mesh.materials().iter().collect()),
+            Self::List(list) => list
+                .objects()
+                .iter()
+                .for_each(|object| object.collect_materials(materials)),
+            Self::BVH(bvh) => bvh
+                .objects()
+                .iter()
+                .for_each(|object| object.collect_materials(materials)),
+            Self::Transform(transform) => transform.object.collect_materials(materials),
+        }
+    }
+
+    pub(crate) fn lights(&self) -> HittableList {
+        let mut lights = HittableList::empty();
+        self.collect_lights(&mut lights);
+        lights
+    }
+
+    /// Turns a list into a BVH, so every instance of the list shares the same acceleration
+    /// structure instead of testing the objects one by one. Everything else is already its own
+    /// bottom-level structure and is returned as is.
+    pub(crate) fn into_bottom_level(self) -> Hittable {
+        match self {
+            Self::List(list) if list.objects().len() > BVH::MAX_PRIMITIVES_PER_LEAF => {
+                Self::BVH(BVH::from_list(list, SplitStrategy::default()))
+            }
+            Self::Transform(transform) if matches!(*transform.object, Self::List(_)) => {
+                let object = transform.object.as_ref().clone().into_bottom_level();
+                Self::Transform(Transform::new(
+                    Arc::new(object),
+                    transform.forward().clone(),
+                ))
+            }
+            _ => self,
+        }
+    }
+
     pub(crate) fn has_geometry(&self) -> bool {
         match self {
             Self::BVH(_) | Self::List(_) => false,
@@ -203,9 +348,11 @@ impl HittableFields {
     }
 }
 
+/// Cloning a list only clones a reference to its objects. They are copied on the first
+/// modification of a clone that still shares them.
 #[derive(Clone, Debug)]
 pub(crate) struct HittableList {
-    objects: Vec<Hittable>,
+    objects: Arc<Vec<Hittable>>,
     bbox: AABB,
 }
 
@@ -216,7 +363,7 @@ impl HittableList {
 
     pub(crate) fn from_vec(objects: Vec<Hittable>) -> HittableList {
         let mut this = Self {
-            objects: vec![],
+            objects: Arc::new(vec![]),
             bbox: AABB::empty(),
         };
 
@@ -229,7 +376,7 @@ impl HittableList {
 
     pub(crate) fn add(&mut self, object: Hittable) {
         self.bbox = AABB::from_boxes(&self.bbox, object.bounding_box());
-        self.objects.push(object);
+        Arc::make_mut(&mut self.objects).push(object);
     }
 
     pub(crate) fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
@@ -244,6 +391,29 @@ impl HittableList {
         })
     }
 
+    /// The average of the densities of the objects, since [`Self::random_direction`] picks
+    /// each object with the same probability.
+    pub(crate) fn pdf_value(&self, origin: &Point, direction: &Vec3D, time: Real) -> Real {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        let weight = 1.0 / self.objects.len() as Real;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction, time))
+            .sum()
+    }
+
+    pub(crate) fn random_direction(&self, origin: &Point, time: Real) -> Vec3D {
+        let index = math::random_int(0, self.objects.len() as i32 - 1) as usize;
+        self.objects[index].random_direction(origin, time)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
     pub(crate) fn make_box(a: Point, b: Point, mat: Material) -> Self {
         let min = Point::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
         let max = Point::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
@@ -307,7 +477,9 @@ impl HittableList {
         &self.objects
     }
 
-    pub(crate) fn objects_mut(&mut self) -> &mut Vec<Hittable> {
-        &mut self.objects
+    /// Whether the two lists still share their objects, i.e. neither has changed since one was
+    /// cloned from the other
+    pub(crate) fn shares_objects(&self, other: &HittableList) -> bool {
+        Arc::ptr_eq(&self.objects, &other.objects)
     }
 }
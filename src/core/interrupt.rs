@@ -0,0 +1,23 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// The exit code of a process killed by SIGINT
+pub(crate) const EXIT_CODE: i32 = 130;
+
+/// Handles SIGINT and SIGTERM by asking the render to stop, so the tiles that are already
+/// finished can still be written. A second signal exits right away.
+pub(crate) fn install_handler() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            log::warn!("Interrupted again. Exiting without writing the image.");
+            process::exit(EXIT_CODE);
+        }
+        log::warn!("Interrupted. Finishing the tiles in progress before writing the image...");
+    })
+}
+
+pub(crate) fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
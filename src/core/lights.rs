@@ -0,0 +1,195 @@
+use crate::core::math::onb::Onb;
+use crate::core::math::vector::UnitVec3D;
+use crate::core::math::{self, Point, Real, Vec3D};
+use crate::core::{Color, HittableList};
+
+/// Light sources without any geometry. The rays never hit them, so they can only light the
+/// scene through the shadow rays sent towards them from every diffuse hit.
+#[derive(Clone, Debug)]
+pub(crate) enum Light {
+    /// Shines equally in every direction from a point, falling off with the squared distance
+    Point {
+        position: Point,
+
+        /// The color times the intensity, in radiant intensity
+        intensity: Color,
+    },
+
+    /// A point light restricted to a cone, fading out between the inner and the outer angle
+    Spot {
+        position: Point,
+
+        /// The axis of the cone
+        direction: UnitVec3D,
+        intensity: Color,
+        cos_inner_angle: Real,
+        cos_outer_angle: Real,
+    },
+
+    /// Parallel light coming from infinitely far away, like the sun. With an angular diameter,
+    /// the light comes from a small disk in the sky, which softens the shadows.
+    Directional {
+        /// The direction the light travels in
+        direction: UnitVec3D,
+
+        /// The color times the intensity, in irradiance
+        intensity: Color,
+
+        /// The cosine of half the angular diameter
+        cos_max_angle: Real,
+    },
+}
+
+/// The light arriving at a point from a [`Light`]
+pub(crate) struct LightSample {
+    /// Towards the light, normalized
+    pub(crate) direction: Vec3D,
+
+    /// How far the light is along `direction`. Infinite for the directional lights.
+    pub(crate) distance: Real,
+
+    /// The irradiance on a surface facing the light
+    pub(crate) irradiance: Color,
+}
+
+impl Light {
+    pub(crate) fn point(position: Point, color: Color, intensity: Real) -> Self {
+        Self::Point {
+            position,
+            intensity: color * intensity,
+        }
+    }
+
+    /// A spot light at `position` pointing at `target`. The angles are in degrees, from the axis
+    /// of the cone.
+    pub(crate) fn spot(
+        position: Point,
+        target: Point,
+        color: Color,
+        intensity: Real,
+        inner_angle: Real,
+        outer_angle: Real,
+    ) -> Self {
+        let outer_angle = outer_angle.max(inner_angle);
+        Self::Spot {
+            direction: (target - &position).to_unit(),
+            position,
+            intensity: color * intensity,
+            cos_inner_angle: math::degrees_to_radians(inner_angle).cos(),
+            cos_outer_angle: math::degrees_to_radians(outer_angle).cos(),
+        }
+    }
+
+    /// A directional light whose rays travel along `direction`. The angular diameter is in
+    /// degrees, and is about 0.53 for the sun.
+    pub(crate) fn directional(
+        direction: Vec3D,
+        color: Color,
+        intensity: Real,
+        angular_diameter: Real,
+    ) -> Self {
+        Self::Directional {
+            direction: direction.to_unit(),
+            intensity: color * intensity,
+            cos_max_angle: math::degrees_to_radians(angular_diameter / 2.0).cos(),
+        }
+    }
+
+    /// Samples the light arriving at `point`, or `None` if the light doesn't reach it.
+    pub(crate) fn sample(&self, point: &Point) -> Option<LightSample> {
+        match self {
+            Self::Point {
+                position,
+                intensity,
+            } => Self::sample_position(point, position, intensity.clone()),
+            Self::Spot {
+                position,
+                direction,
+                intensity,
+                cos_inner_angle,
+                cos_outer_angle,
+            } => {
+                let cos_theta = direction.dot(&(point - position).to_unit().0);
+                let falloff = smoothstep(*cos_outer_angle, *cos_inner_angle, cos_theta);
+                if falloff <= 0.0 {
+                    return None;
+                }
+                Self::sample_position(point, position, intensity * falloff)
+            }
+            Self::Directional {
+                direction,
+                intensity,
+                cos_max_angle,
+            } => {
+                let towards_light = -&direction.0;
+                let direction = if *cos_max_angle < 1.0 {
+                    // uniformly within the disk of the light, as seen from the point
+                    let (u, v) = math::random_pair();
+                    let cos_theta = 1.0 - u * (1.0 - cos_max_angle);
+                    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                    let phi = 2.0 * math::PI * v;
+                    Onb::new(&towards_light).transform(&Vec3D::new(
+                        phi.cos() * sin_theta,
+                        phi.sin() * sin_theta,
+                        cos_theta,
+                    ))
+                } else {
+                    towards_light
+                };
+
+                Some(LightSample {
+                    direction,
+                    distance: math::INFINITY,
+                    irradiance: intensity.clone(),
+                })
+            }
+        }
+    }
+
+    fn sample_position(point: &Point, position: &Point, intensity: Color) -> Option<LightSample> {
+        let towards_light = position - point;
+        let distance_squared = towards_light.length_squared();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+
+        let distance = distance_squared.sqrt();
+        Some(LightSample {
+            direction: towards_light / distance,
+            distance,
+            irradiance: intensity / distance_squared,
+        })
+    }
+}
+
+/// 0 below `start`, 1 above `end`, and a smooth transition in between
+fn smoothstep(start: Real, end: Real, x: Real) -> Real {
+    if start >= end {
+        return if x >= end { 1.0 } else { 0.0 };
+    }
+    let t = ((x - start) / (end - start)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Everything in a scene that gets sampled directly
+pub(crate) struct Lights {
+    /// The emissive objects, which the rays can also hit
+    objects: HittableList,
+
+    /// The light sources without geometry
+    sources: Vec<Light>,
+}
+
+impl Lights {
+    pub(crate) fn new(objects: HittableList, sources: Vec<Light>) -> Self {
+        Self { objects, sources }
+    }
+
+    pub(crate) fn objects(&self) -> &HittableList {
+        &self.objects
+    }
+
+    pub(crate) fn sources(&self) -> &[Light] {
+        &self.sources
+    }
+}
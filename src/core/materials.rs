@@ -1,35 +1,128 @@
 use crate::core::hittables::HitRecord;
+use crate::core::math::onb::Onb;
+use crate::core::math::vector::UnitVec3D;
 use crate::core::math::{Point, Real, Vec3D};
+use crate::core::microfacet::TrowbridgeReitz;
+use crate::core::pdf::Pdf;
 use crate::core::textures::{SolidColor, Texture};
 use crate::core::{math, Color, Ray};
+use serde::Deserialize;
+use std::sync::Arc;
 
+/// A material, shared by all the shapes it is given to. Copies of a material are the same
+/// material, while materials created separately are different ones, even with the same
+/// parameters.
 #[derive(Clone, Debug)]
-pub(crate) enum Material {
+pub(crate) struct Material {
+    kind: Arc<MaterialKind>,
+}
+
+#[derive(Debug)]
+pub(crate) enum MaterialKind {
     Lambertian(Lambertian),
     Metal(Metal),
+    Conductor(Conductor),
     Dielectric(Dielectric),
     DiffuseLight(DiffuseLight),
     Isotropic(Isotropic),
 }
 
+/// How a material scattered an incoming ray.
+pub(crate) struct ScatterRecord {
+    pub(crate) attenuation: Color,
+    pub(crate) scattered: Scattered,
+}
+
+pub(crate) enum Scattered {
+    /// The outgoing direction follows a probability density, so it can be sampled together
+    /// with the lights and weighted through [`Material::scattering_pdf`].
+    Pdf(Pdf<'static>),
+
+    /// The outgoing direction is a delta function (e.g. mirrors and glass), so the ray
+    /// is followed as is and skips the density calculations.
+    Specular(Ray),
+}
+
+impl ScatterRecord {
+    fn from_pdf(attenuation: Color, pdf: Pdf<'static>) -> Self {
+        Self {
+            attenuation,
+            scattered: Scattered::Pdf(pdf),
+        }
+    }
+
+    fn specular(attenuation: Color, ray: Ray) -> Self {
+        Self {
+            attenuation,
+            scattered: Scattered::Specular(ray),
+        }
+    }
+}
+
 impl Material {
-    pub(crate) fn scatter<'a, 'b, 'c>(
-        &self,
-        ray_in: &Ray,
-        rec: &'b HitRecord,
-    ) -> Option<(Ray, Color)> {
-        match self {
-            Self::Lambertian(lambertian) => Some(lambertian.scatter(ray_in, rec)),
-            Self::Metal(metal) => Some(metal.scatter(ray_in, rec)),
-            Self::Dielectric(dielectric) => Some(dielectric.scatter(ray_in, rec)),
-            Self::Isotropic(isotropic) => Some(isotropic.scatter(ray_in, rec)),
-            Self::DiffuseLight(_) => None,
+    pub(crate) fn new(kind: MaterialKind) -> Self {
+        Self {
+            kind: Arc::new(kind),
+        }
+    }
+
+    pub(crate) fn kind(&self) -> &MaterialKind {
+        &self.kind
+    }
+
+    /// Where the material is in memory, which is the same for all of its copies
+    pub(crate) fn address(&self) -> usize {
+        Arc::as_ptr(&self.kind) as usize
+    }
+
+    pub(crate) fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        match self.kind() {
+            MaterialKind::Lambertian(lambertian) => Some(lambertian.scatter(rec)),
+            MaterialKind::Metal(metal) => Some(metal.scatter(ray_in, rec)),
+            MaterialKind::Conductor(conductor) => Some(conductor.scatter(ray_in, rec)),
+            MaterialKind::Dielectric(dielectric) => dielectric.scatter(ray_in, rec),
+            MaterialKind::Isotropic(isotropic) => Some(isotropic.scatter(rec)),
+            MaterialKind::DiffuseLight(_) => None,
         }
     }
 
     pub(crate) fn emitted(&self, u: Real, v: Real, p: &Point) -> Color {
-        match self {
-            Self::DiffuseLight(diffuse_light) => diffuse_light.emitted(u, v, p),
+        match self.kind() {
+            MaterialKind::DiffuseLight(diffuse_light) => diffuse_light.emitted(u, v, p),
+            _ => Color::black(),
+        }
+    }
+
+    /// The fraction of the light the material reflects at the hit point, regardless of the
+    /// direction. Lights don't reflect anything.
+    pub(crate) fn albedo(&self, rec: &HitRecord) -> Color {
+        match self.kind() {
+            MaterialKind::Lambertian(Lambertian { texture })
+            | MaterialKind::Isotropic(Isotropic { texture }) => {
+                texture.value(rec.u(), rec.v(), rec.p())
+            }
+            MaterialKind::Metal(metal) => metal.albedo.clone(),
+            MaterialKind::Conductor(conductor) => conductor.fresnel(1.0),
+            MaterialKind::Dielectric(_) => Color::white(),
+            MaterialKind::DiffuseLight(_) => Color::black(),
+        }
+    }
+
+    pub(crate) fn is_emissive(&self) -> bool {
+        matches!(self.kind(), MaterialKind::DiffuseLight(_))
+    }
+
+    /// What the light coming from the direction of `scattered` gets multiplied by, on top of
+    /// the attenuation, when the material scatters it back along `ray_in`. For the materials
+    /// whose color doesn't depend on the direction, it is the probability density of the
+    /// scattering in every channel. Only meaningful for materials that scatter through a [`Pdf`].
+    pub(crate) fn scattering(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        match self.kind() {
+            MaterialKind::Lambertian(_) => {
+                Color::from_scalar(Lambertian::scattering_pdf(rec, scattered))
+            }
+            MaterialKind::Conductor(conductor) => conductor.scattering(ray_in, rec, scattered),
+            MaterialKind::Isotropic(_) => Color::from_scalar(Isotropic::scattering_pdf()),
             _ => Color::black(),
         }
     }
@@ -49,16 +142,15 @@ impl Lambertian {
         Self::from_texture(Texture::SolidColor(SolidColor::new(albedo)))
     }
 
-    fn scatter<'a>(&self, ray_in: &Ray, rec: &'a HitRecord) -> (Ray, Color) {
-        let scatter_direction = &rec.normal().0 + Vec3D::random_unit().0;
-        let scatter_direction = if scatter_direction.near_zero() {
-            rec.normal().0.clone()
-        } else {
-            scatter_direction
-        };
-        let scattered = Ray::new_timed(rec.p().clone(), scatter_direction, ray_in.time());
+    /// Lambertian reflection scatters proportionally to the cosine of the angle from the normal.
+    fn scattering_pdf(rec: &HitRecord, scattered: &Ray) -> Real {
+        let cosine = rec.normal().dot(&scattered.direction().to_unit().0);
+        (cosine / math::PI).max(0.0)
+    }
+
+    fn scatter(&self, rec: &HitRecord) -> ScatterRecord {
         let attenuation = self.texture.value(rec.u(), rec.v(), rec.p());
-        (scattered, attenuation)
+        ScatterRecord::from_pdf(attenuation, Pdf::cosine(&rec.normal().0))
     }
 }
 
@@ -76,26 +168,209 @@ impl Metal {
         }
     }
 
-    fn scatter<'a>(&self, ray_in: &Ray, rec: &'a HitRecord) -> (Ray, Color) {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> ScatterRecord {
         let reflected = ray_in.direction().reflect(&rec.normal());
         let reflected = reflected.to_unit().0 + Vec3D::random_unit().0 * self.fuzz;
         let scattered = Ray::new_timed(rec.p().clone(), reflected, ray_in.time());
-        let attenuation = self.albedo.clone();
-        (scattered, attenuation)
+        ScatterRecord::specular(self.albedo.clone(), scattered)
+    }
+}
+
+/// A metal with a rough surface, made of microfacets distributed according to the
+/// Trowbridge-Reitz (GGX) distribution. The Fresnel reflectance comes from the complex index of
+/// refraction of the metal: `eta` and the extinction coefficient `k`, for every color channel.
+#[derive(Clone, Debug)]
+pub(crate) struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    /// The roughnesses go from 0 (a mirror) to 1, along the two tangents of the surface. They
+    /// only differ for anisotropic metals, like brushed ones.
+    pub(crate) fn new(eta: Color, k: Color, roughness_u: Real, roughness_v: Real) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::from_roughness(roughness_u, roughness_v),
+        }
+    }
+
+    pub(crate) fn from_preset(
+        preset: ConductorPreset,
+        roughness_u: Real,
+        roughness_v: Real,
+    ) -> Self {
+        let (eta, k) = preset.complex_ior();
+        Self::new(eta, k, roughness_u, roughness_v)
+    }
+
+    /// Derives the complex index of refraction from the colors of the reflection at normal
+    /// incidence and towards the grazing angles (Gulbrandsen, "Artist Friendly Metallic
+    /// Fresnel").
+    pub(crate) fn from_edge_tint(
+        reflectivity: Color,
+        edge_tint: Color,
+        roughness_u: Real,
+        roughness_v: Real,
+    ) -> Self {
+        let channel = |r: Real, g: Real| {
+            let r = r.clamp(0.0, 0.99);
+            let eta = g * (1.0 - r) / (1.0 + r) + (1.0 - g) * (1.0 + r.sqrt()) / (1.0 - r.sqrt());
+            let k = ((r * (eta + 1.0).powi(2) - (eta - 1.0).powi(2)) / (1.0 - r))
+                .max(0.0)
+                .sqrt();
+            (eta, k)
+        };
+        let (eta_r, k_r) = channel(reflectivity.x, edge_tint.x);
+        let (eta_g, k_g) = channel(reflectivity.y, edge_tint.y);
+        let (eta_b, k_b) = channel(reflectivity.z, edge_tint.z);
+        Self::new(
+            Color::new(eta_r, eta_g, eta_b),
+            Color::new(k_r, k_g, k_b),
+            roughness_u,
+            roughness_v,
+        )
+    }
+
+    /// Samples the visible normals of the microfacets, oriented by the tangent of the surface.
+    /// The Fresnel reflectance depends on the microfacet, so it is left to
+    /// [`Self::scattering`], except for smooth metals whose only microfacet is the surface.
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> ScatterRecord {
+        if self.distribution.is_smooth() {
+            let unit_direction = ray_in.direction().to_unit();
+            let cos_theta = (-&unit_direction.0).dot(&rec.normal().0).clamp(0.0, 1.0);
+            let reflected = unit_direction.reflect(rec.normal());
+            let scattered = Ray::new_timed(rec.p().clone(), reflected, ray_in.time());
+            return ScatterRecord::specular(self.fresnel(cos_theta), scattered);
+        }
+
+        let pdf = Pdf::microfacet(
+            rec.shading_frame(),
+            ray_in.direction(),
+            self.distribution.clone(),
+        );
+        ScatterRecord::from_pdf(Color::white(), pdf)
+    }
+
+    /// The microfacet reflection towards `scattered`, with the Fresnel reflectance of the
+    /// microfacets that reflect `ray_in` there
+    fn scattering(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let onb = rec.shading_frame();
+        let wo = onb.to_local(&-ray_in.direction().to_unit().0);
+        let wi = onb.to_local(&scattered.direction().to_unit().0);
+        let reflection = self.distribution.reflection(&wo, &wi);
+        if reflection <= 0.0 {
+            return Color::black();
+        }
+
+        let half = (&wo + &wi).to_unit();
+        self.fresnel(wo.dot(&half.0).clamp(0.0, 1.0)) * reflection
+    }
+
+    /// The fraction of the light reflected at an angle whose cosine is `cos_theta`
+    fn fresnel(&self, cos_theta: Real) -> Color {
+        Color::new(
+            fresnel_conductor(cos_theta, self.eta.x, self.k.x),
+            fresnel_conductor(cos_theta, self.eta.y, self.k.y),
+            fresnel_conductor(cos_theta, self.eta.z, self.k.z),
+        )
     }
 }
 
+/// The Fresnel reflectance of a conductor for unpolarized light, in a single channel
+fn fresnel_conductor(cos_theta: Real, eta: Real, k: Real) -> Real {
+    let cos2 = cos_theta * cos_theta;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    (0.5 * (rp + rs)).clamp(0.0, 1.0)
+}
+
+/// Metals with a measured complex index of refraction, at the wavelengths of the red, green and
+/// blue channels
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConductorPreset {
+    Gold,
+    Silver,
+    Copper,
+    Aluminum,
+    Iron,
+    Chromium,
+}
+
+impl ConductorPreset {
+    /// `eta` and `k`
+    #[allow(clippy::approx_constant)]
+    fn complex_ior(&self) -> (Color, Color) {
+        let (eta, k) = match self {
+            Self::Gold => ([0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+            Self::Silver => ([0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+            Self::Copper => ([0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+            Self::Aluminum => ([1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+            Self::Iron => ([2.868, 2.917, 2.589], [3.077, 2.929, 2.775]),
+            Self::Chromium => ([3.180, 3.180, 2.300], [3.300, 3.330, 3.140]),
+        };
+        (
+            Color::new(eta[0], eta[1], eta[2]),
+            Color::new(k[0], k[1], k[2]),
+        )
+    }
+}
+
+/// Glass and other transparent materials. A rough surface frosts the glass, through the same
+/// microfacet distribution as [`Conductor`], and the absorption tints the light according to the
+/// distance it travels inside (Beer-Lambert). That distance is only known when a ray hits the
+/// surface from the inside, since the medium the ray is in isn't tracked, so nothing placed inside
+/// the dielectric gets tinted.
 #[derive(Clone, Debug)]
 pub(crate) struct Dielectric {
     refraction_index: Real,
+    distribution: TrowbridgeReitz,
+
+    /// The fraction of the light absorbed per unit distance, for every color channel
+    absorption_coefficient: Color,
 }
 
 impl Dielectric {
     pub(crate) fn new(refraction_index: Real) -> Self {
-        Self { refraction_index }
+        Self::rough_absorbing(refraction_index, 0.0, Color::white())
     }
 
-    fn scatter<'a>(&self, ray_in: &Ray, rec: &'a HitRecord) -> (Ray, Color) {
+    /// `roughness` goes from 0 (polished) to 1. `absorption` is the color the light takes after
+    /// traveling one unit of distance inside, and is white for clear glass.
+    pub(crate) fn rough_absorbing(
+        refraction_index: Real,
+        roughness: Real,
+        absorption: Color,
+    ) -> Self {
+        let coefficient = |transmittance: Real| -transmittance.clamp(1e-6, 1.0).ln();
+        Self {
+            refraction_index,
+            distribution: TrowbridgeReitz::from_roughness(roughness, roughness),
+            absorption_coefficient: Color::new(
+                coefficient(absorption.x),
+                coefficient(absorption.y),
+                coefficient(absorption.z),
+            ),
+        }
+    }
+
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
         let ri = if rec.front_face() {
             1.0 / self.refraction_index
         } else {
@@ -103,20 +378,77 @@ impl Dielectric {
         };
         let unit_direction = ray_in.direction().to_unit();
 
-        let cos_theta = (-&unit_direction.0).dot(&rec.normal().0).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let (direction, attenuation) = if self.distribution.is_smooth() {
+            let cos_theta = (-&unit_direction.0).dot(&rec.normal().0).min(1.0);
+            (
+                Self::reflect_or_refract(&unit_direction, rec.normal(), cos_theta, ri),
+                Color::white(),
+            )
+        } else {
+            self.scatter_rough(&unit_direction, rec.normal(), ri)?
+        };
 
-        let cannot_refract = ri * sin_theta > 1.0;
-        let direction = if cannot_refract || Self::reflectance(cos_theta, ri) > math::random_real()
-        {
-            unit_direction.reflect(rec.normal())
+        // hitting the surface from the inside means the ray went all the way through the medium
+        let attenuation = if rec.front_face() {
+            attenuation
         } else {
-            Vec3D::refract(&unit_direction, rec.normal(), ri)
+            let distance = rec.t() * ray_in.direction().length();
+            let transmittance = Color::new(
+                (-self.absorption_coefficient.x * distance).exp(),
+                (-self.absorption_coefficient.y * distance).exp(),
+                (-self.absorption_coefficient.z * distance).exp(),
+            );
+            attenuation * transmittance
         };
 
         let scattered = Ray::new_timed(rec.p().clone(), direction, ray_in.time());
+        Some(ScatterRecord::specular(attenuation, scattered))
+    }
+
+    /// Picks the reflection or the refraction about `normal` according to the reflectance
+    fn reflect_or_refract(
+        unit_direction: &UnitVec3D,
+        normal: &UnitVec3D,
+        cos_theta: Real,
+        ri: Real,
+    ) -> Vec3D {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
-        (scattered, Color::white())
+        let cannot_refract = ri * sin_theta > 1.0;
+        if cannot_refract || Self::reflectance(cos_theta, ri) > math::random_real() {
+            unit_direction.reflect(normal)
+        } else {
+            Vec3D::refract(unit_direction, normal, ri)
+        }
+    }
+
+    /// Reflects or refracts about the normal of a visible microfacet. Weighting by the masking
+    /// of the outgoing direction is all it takes for the sample to follow the rough surface's
+    /// scattering. `None` if the direction ends up on the wrong side of the surface.
+    fn scatter_rough(
+        &self,
+        unit_direction: &UnitVec3D,
+        normal: &UnitVec3D,
+        ri: Real,
+    ) -> Option<(Vec3D, Color)> {
+        let onb = Onb::new(&normal.0);
+        let wo = onb.to_local(&-&unit_direction.0);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let half = UnitVec3D(self.distribution.sample_visible_normal(&wo));
+        let cos_theta = wo.dot(&half.0).min(1.0);
+        let wi = Self::reflect_or_refract(&UnitVec3D(-wo), &half, cos_theta, ri);
+
+        // reflections stay above the surface, and refractions go below
+        let reflected = wi.dot(&half.0) > 0.0;
+        if reflected != (wi.z > 0.0) {
+            return None;
+        }
+
+        let weight = self.distribution.g1(&wi);
+        Some((onb.transform(&wi), Color::from_scalar(weight)))
     }
 
     /// Computes the reflectance using Schlick's Approximation
@@ -170,6 +502,19 @@ pub(crate) mod refractive_index {
     pub(crate) const AIR: Real = 1.0003;
     pub(crate) const WATER: Real = 1.333;
     pub(crate) const DIAMOND: Real = 2.417;
+    pub(crate) const ICE: Real = 1.309;
+    pub(crate) const ETHANOL: Real = 1.361;
+    pub(crate) const OLIVE_OIL: Real = 1.47;
+    pub(crate) const ACRYLIC: Real = 1.49;
+    pub(crate) const CROWN_GLASS: Real = 1.52;
+    pub(crate) const QUARTZ: Real = 1.544;
+    pub(crate) const AMBER: Real = 1.55;
+    pub(crate) const EMERALD: Real = 1.57;
+    pub(crate) const POLYCARBONATE: Real = 1.586;
+    pub(crate) const FLINT_GLASS: Real = 1.62;
+    pub(crate) const SAPPHIRE: Real = 1.77;
+    pub(crate) const CUBIC_ZIRCONIA: Real = 2.15;
+    pub(crate) const MOISSANITE: Real = 2.65;
 }
 
 #[derive(Clone, Debug)]
@@ -186,15 +531,15 @@ impl Isotropic {
         Self { texture }
     }
 
-    pub(crate) fn scatter<'a>(&self, ray_in: &Ray, hit_record: &'a HitRecord) -> (Ray, Color) {
-        let scattered = Ray::new_timed(
-            hit_record.p().clone(),
-            Vec3D::random_unit().0,
-            ray_in.time(),
-        );
+    /// Isotropic media scatter uniformly in all directions.
+    fn scattering_pdf() -> Real {
+        1.0 / (4.0 * math::PI)
+    }
+
+    fn scatter(&self, hit_record: &HitRecord) -> ScatterRecord {
         let attenuation = self
             .texture
             .value(hit_record.u(), hit_record.v(), hit_record.p());
-        (scattered, attenuation)
+        ScatterRecord::from_pdf(attenuation, Pdf::Sphere)
     }
 }
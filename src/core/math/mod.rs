@@ -1,4 +1,7 @@
-use rand::Rng;
+use crate::core::sampler::Sampler;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+use std::cell::RefCell;
 use std::ops::{Add, Mul};
 
 pub(crate) mod interval;
@@ -6,6 +9,7 @@ pub(crate) mod macros;
 pub(crate) mod vector;
 pub(crate) mod ray;
 pub(crate) mod matrix;
+pub(crate) mod onb;
 mod tuple;
 pub(crate) mod point;
 
@@ -17,7 +21,6 @@ pub(crate) use vector::VecLike;
 
 pub(crate) const INFINITY: Real = Real::INFINITY;
 pub(crate) const PI: Real = std::f64::consts::PI;
-pub(crate) const GAMMA: f32 = 2.2;
 pub(crate) const EPSILON: f64 = 1e-8;
 
 pub(crate) use matrix::Matrix;
@@ -33,13 +36,45 @@ where
     (value + 1.0) * 0.5
 }
 
-pub(crate) fn random_range(min: Real, max: Real) -> Real {
-    let mut rng = rand::rng();
-    rng.random_range(min..max)
+thread_local! {
+    /// The generator behind all the random numbers. Each thread has its own, and the renderer
+    /// reseeds it for every sample, so the results don't depend on how the work is scheduled.
+    static RNG: RefCell<Pcg64Mcg> = RefCell::new(Pcg64Mcg::seed_from_u64(0));
+}
+
+/// Restarts the random numbers of the current thread from `seed`.
+pub(crate) fn seed_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = Pcg64Mcg::seed_from_u64(seed));
 }
 
+/// Derives a new seed from `seed` and `value` (SplitMix64's finalizer), so that streams
+/// seeded with nearby values aren't correlated.
+pub(crate) fn mix_seed(seed: u64, value: u64) -> u64 {
+    let mut z = (seed ^ value).wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// A random number in `[0, 1)`. While the renderer takes a sample, it is the next dimension of
+/// the camera's [`Sampler`].
 pub(crate) fn random_real() -> Real {
-    random_range(0.0, 1.0)
+    Sampler::next_1d().unwrap_or_else(|| RNG.with(|rng| rng.borrow_mut().random::<Real>()))
+}
+
+/// Two random numbers in `[0, 1)`, which the [`Sampler`] distributes together. Anything that maps
+/// two numbers to a point (on a disk, a square, a hemisphere...) should use this.
+pub(crate) fn random_pair() -> (Real, Real) {
+    Sampler::next_2d().unwrap_or_else(|| {
+        RNG.with(|rng| {
+            let mut rng = rng.borrow_mut();
+            (rng.random::<Real>(), rng.random::<Real>())
+        })
+    })
+}
+
+pub(crate) fn random_range(min: Real, max: Real) -> Real {
+    min + (max - min) * random_real()
 }
 
 pub(crate) fn random_int(min: i32, max: i32) -> i32 {
@@ -85,6 +120,14 @@ pub(crate) fn discriminant(a: Real, b: Real, c: Real) -> Real {
     b * b - 4.0 * a * c
 }
 
+/// The power heuristic (with an exponent of 2) for weighting a sample taken from a strategy
+/// with density `pdf` against another strategy with density `other_pdf`.
+pub(crate) fn power_heuristic(pdf: Real, other_pdf: Real) -> Real {
+    let pdf_squared = pdf * pdf;
+    let sum = pdf_squared + other_pdf * other_pdf;
+    if sum > 0.0 { pdf_squared / sum } else { 0.0 }
+}
+
 pub(crate) fn root(a: Real, b: Real, sqrt_d: Real) -> Real {
     (-b + sqrt_d) / (2.0 * a)
 }
\ No newline at end of file
@@ -0,0 +1,57 @@
+use crate::core::math::Vec3D;
+use crate::core::math::vector::UnitVec3D;
+
+/// Orthonormal basis. This is used to turn directions generated around the z-axis into
+/// directions around an arbitrary axis (`w`).
+#[derive(Clone, Debug)]
+pub(crate) struct Onb {
+    u: UnitVec3D,
+    v: UnitVec3D,
+    w: UnitVec3D,
+}
+
+impl Onb {
+    pub(crate) fn new(w: &Vec3D) -> Self {
+        let w = w.to_unit();
+
+        // pick any vector that isn't parallel to `w`
+        let a = if w.x.abs() > 0.9 {
+            Vec3D::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3D::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).to_unit();
+        let u = UnitVec3D(w.cross(&v));
+
+        Self { u, v, w }
+    }
+
+    /// A basis whose `u` axis is `tangent`, made perpendicular to `w`. Any basis around `w`
+    /// does if `tangent` is parallel to it.
+    pub(crate) fn with_tangent(w: &Vec3D, tangent: &Vec3D) -> Self {
+        let w = w.to_unit();
+        let u = tangent - &w.0 * w.dot(tangent);
+        if u.length_squared() <= 1e-12 * tangent.length_squared() {
+            return Self::new(&w.0);
+        }
+        let u = u.to_unit();
+        let v = UnitVec3D(w.cross(&u.0));
+
+        Self { u, v, w }
+    }
+
+    pub(crate) fn w(&self) -> &UnitVec3D {
+        &self.w
+    }
+
+    /// Converts a vector in world coordinates into this basis, the inverse of
+    /// [`Self::transform`].
+    pub(crate) fn to_local(&self, world: &Vec3D) -> Vec3D {
+        Vec3D::new(self.u.dot(world), self.v.dot(world), self.w.dot(world))
+    }
+
+    /// Converts a vector expressed in this basis into world coordinates.
+    pub(crate) fn transform(&self, local: &Vec3D) -> Vec3D {
+        &self.u.0 * local.x + &self.v.0 * local.y + &self.w.0 * local.z
+    }
+}
@@ -96,15 +96,6 @@ impl Vec3D {
         UnitVec3D(self / self.length())
     }
 
-    pub(crate) fn random_on_hemisphere(normal: &UnitVec3D) -> UnitVec3D {
-        let on_unit_sphere = Self::random_unit();
-        if on_unit_sphere.dot(&normal.0) > 0.0 {
-            on_unit_sphere
-        } else {
-            UnitVec3D(-on_unit_sphere.0)
-        }
-    }
-
     pub(crate) fn reflect(&self, normal: &UnitVec3D) -> Vec3D {
         self - &normal.0 * 2.0 * self.dot(&normal)
     }
@@ -121,41 +112,57 @@ impl Vec3D {
         refracted_perpendicular + refracted_parallel
     }
 
-    pub(crate) fn near_zero(&self) -> bool {
-        math::near_zero(self.x.abs())
-            && math::near_zero(self.y.abs())
-            && math::near_zero(self.z.abs())
-    }
-
     pub(crate) fn random_unit() -> UnitVec3D {
-        loop {
-            let vec = Vec3D::random_range(-1.0, 1.0);
+        let (r1, r2) = math::random_pair();
 
-            // not using the magnitude directly to delay computation for sqrt
-            let len_squared = vec.length_squared();
-
-            // we check for 1e-160 to avoid producing a 0 sqrt if the length
-            // squared is too small, which can cause division issues
-            if 1e-160 < len_squared && len_squared <= 1.0 {
-                return UnitVec3D(vec / len_squared.sqrt());
-            }
-        }
+        // z is uniform on a sphere (Archimedes' hat-box theorem)
+        let z = 1.0 - 2.0 * r1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * math::PI * r2;
+        UnitVec3D(Vec3D::new(r * phi.cos(), r * phi.sin(), z))
     }
 
     /// Returns a random vector. Note that unlike [[Self::random_unit]], the
     /// vector generated by this function is only for two dimensions (z component
     /// is zero)
     pub(crate) fn random_in_unit_disk() -> Vec3D {
-        loop {
-            let v = Vec3D::new(
-                math::random_range(-1.0, 1.0),
-                math::random_range(-1.0, 1.0),
-                0.0,
-            );
-            if v.length_squared() < 1.0 {
-                return v;
-            }
+        let (r1, r2) = math::random_pair();
+        let (x, y) = (2.0 * r1 - 1.0, 2.0 * r2 - 1.0);
+        if x == 0.0 && y == 0.0 {
+            return Vec3D::zero();
         }
+
+        // Shirley and Chiu's concentric mapping, which keeps the stratification of the square
+        let (r, theta) = if x.abs() > y.abs() {
+            (x, math::PI / 4.0 * (y / x))
+        } else {
+            (y, math::PI / 2.0 - math::PI / 4.0 * (x / y))
+        };
+        Vec3D::new(r * theta.cos(), r * theta.sin(), 0.0)
+    }
+
+    /// Returns a random direction around the z-axis, with a density proportional to the cosine
+    /// of the angle from the axis.
+    pub(crate) fn random_cosine_direction() -> Vec3D {
+        let (r1, r2) = math::random_pair();
+
+        let phi = 2.0 * math::PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1.0 - r2).sqrt();
+        Vec3D::new(x, y, z)
+    }
+
+    /// Returns a random direction, around the z-axis, towards a sphere of radius `radius`
+    /// whose center is `distance_squared` away. The directions are uniformly distributed
+    /// within the cone subtended by the sphere.
+    pub(crate) fn random_to_sphere(radius: Real, distance_squared: Real) -> Vec3D {
+        let (r1, r2) = math::random_pair();
+        let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+        let phi = 2.0 * math::PI * r1;
+        let sin_theta = (1.0 - z * z).sqrt();
+        Vec3D::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
     }
 
     define_tuple_conversion!();
@@ -0,0 +1,231 @@
+use crate::core::math::{self, Real, Vec3D};
+
+/// The narrowest the distribution gets. At this width, a surface is considered perfectly smooth
+/// and scatters like a mirror, since the distribution gets too sharp to be evaluated reliably.
+const MIN_ALPHA: Real = 1e-3;
+
+/// The Trowbridge-Reitz (GGX) distribution of the normals of the microfacets of a rough surface.
+/// Every direction is in the local space of the surface: `x` and `y` along the tangents, and `z`
+/// along the normal.
+#[derive(Clone, Debug)]
+pub(crate) struct TrowbridgeReitz {
+    /// The width of the distribution along the first tangent
+    alpha_x: Real,
+
+    /// The width of the distribution along the second tangent
+    alpha_y: Real,
+}
+
+impl TrowbridgeReitz {
+    /// The roughnesses go from 0 (smooth) to 1, and are squared into the widths of the
+    /// distribution so they look perceptually linear.
+    pub(crate) fn from_roughness(roughness_x: Real, roughness_y: Real) -> Self {
+        let alpha = |roughness: Real| roughness.clamp(0.0, 1.0).powi(2).max(MIN_ALPHA);
+        Self {
+            alpha_x: alpha(roughness_x),
+            alpha_y: alpha(roughness_y),
+        }
+    }
+
+    pub(crate) fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) <= MIN_ALPHA
+    }
+
+    /// The density of the microfacets whose normal is `half`
+    pub(crate) fn d(&self, half: &Vec3D) -> Real {
+        let cos_theta = half.z;
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let e =
+            (half.x / self.alpha_x).powi(2) + (half.y / self.alpha_y).powi(2) + cos_theta.powi(2);
+        1.0 / (math::PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    /// The auxiliary function of Smith's masking, from which both forms of `g` derive
+    fn lambda(&self, w: &Vec3D) -> Real {
+        let cos_theta_squared = w.z * w.z;
+        if cos_theta_squared <= 0.0 {
+            return math::INFINITY;
+        }
+
+        let tan_theta_squared =
+            ((self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2)) / cos_theta_squared;
+        ((1.0 + tan_theta_squared).sqrt() - 1.0) / 2.0
+    }
+
+    /// The fraction of the microfacets that are visible from `w`
+    pub(crate) fn g1(&self, w: &Vec3D) -> Real {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// The fraction of the microfacets that are visible from both `wo` and `wi`
+    pub(crate) fn g(&self, wo: &Vec3D, wi: &Vec3D) -> Real {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Samples the normal of a microfacet visible from `wo`, in proportion to its projected area
+    /// (Heitz, "Sampling the GGX Distribution of Visible Normals").
+    pub(crate) fn sample_visible_normal(&self, wo: &Vec3D) -> Vec3D {
+        // stretch the view so the distribution becomes the hemisphere of unit roughness
+        let wh = Vec3D::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z)
+            .to_unit()
+            .0;
+        let length_squared = wh.x * wh.x + wh.y * wh.y;
+        let t1 = if length_squared > 0.0 {
+            Vec3D::new(-wh.y, wh.x, 0.0) / length_squared.sqrt()
+        } else {
+            Vec3D::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        // a point on the disk, squashed onto the part of it that is visible
+        let (u, v) = math::random_pair();
+        let r = u.sqrt();
+        let phi = 2.0 * math::PI * v;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + wh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        let nh = t1 * p1 + t2 * p2 + wh * p3;
+
+        // and back to the original roughness
+        Vec3D::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(0.0))
+            .to_unit()
+            .0
+    }
+
+    /// The density of sampling a normal with [`Self::sample_visible_normal`] and reflecting
+    /// `wo` about it into `wi`
+    pub(crate) fn reflection_pdf(&self, wo: &Vec3D, wi: &Vec3D) -> Real {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let half = (wo + wi).to_unit().0;
+        self.g1(wo) * self.d(&half) / (4.0 * wo.z)
+    }
+
+    /// The reflection of the microfacet model without the Fresnel term, times the cosine of
+    /// `wi`: what gets multiplied by the light coming from `wi`.
+    pub(crate) fn reflection(&self, wo: &Vec3D, wi: &Vec3D) -> Real {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let half = (wo + wi).to_unit().0;
+        self.d(&half) * self.g(wo, wi) / (4.0 * wo.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrowbridgeReitz;
+    use crate::core::math::vector::UnitVec3D;
+    use crate::core::math::{self, Real, Vec3D};
+
+    const THETAS: usize = 8;
+    const PHIS: usize = 16;
+
+    fn direction(theta: Real, phi: Real) -> Vec3D {
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        Vec3D::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+
+    /// The bin of the hemisphere that `w` is in
+    fn bin(w: &Vec3D) -> usize {
+        let theta = w.z.clamp(0.0, 1.0).acos();
+        let phi = w.y.atan2(w.x).rem_euclid(2.0 * math::PI);
+        let i = ((theta / (math::PI / 2.0) * THETAS as Real) as usize).min(THETAS - 1);
+        let j = ((phi / (2.0 * math::PI) * PHIS as Real) as usize).min(PHIS - 1);
+        i * PHIS + j
+    }
+
+    /// The integral of [`TrowbridgeReitz::reflection_pdf`] over every bin of the hemisphere
+    fn integrate_pdf(distribution: &TrowbridgeReitz, wo: &Vec3D) -> Vec<Real> {
+        let (thetas, phis) = (THETAS * 64, PHIS * 64);
+        let d_theta = math::PI / 2.0 / thetas as Real;
+        let d_phi = 2.0 * math::PI / phis as Real;
+
+        let mut bins = vec![0.0; THETAS * PHIS];
+        for i in 0..thetas {
+            let theta = (i as Real + 0.5) * d_theta;
+            for j in 0..phis {
+                let phi = (j as Real + 0.5) * d_phi;
+                let wi = direction(theta, phi);
+                bins[bin(&wi)] +=
+                    distribution.reflection_pdf(wo, &wi) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        bins
+    }
+
+    /// The fraction of the reflections about [`TrowbridgeReitz::sample_visible_normal`] that
+    /// fall in every bin of the hemisphere
+    fn sample_histogram(distribution: &TrowbridgeReitz, wo: &Vec3D, count: usize) -> Vec<Real> {
+        let mut bins = vec![0.0; THETAS * PHIS];
+        for _ in 0..count {
+            let half = distribution.sample_visible_normal(wo);
+            let wi = (-wo).reflect(&UnitVec3D(half));
+            if wi.z > 0.0 {
+                bins[bin(&wi)] += 1.0 / count as Real;
+            }
+        }
+        bins
+    }
+
+    #[test]
+    fn test_reflection_pdf_integrates_to_one() {
+        // straight on, the reflections off the microfacets tilted by more than 45 degrees go
+        // below the horizon, which is a fraction 1 - 1 / (1 + alpha²) of them
+        let wo = Vec3D::new(0.0, 0.0, 1.0);
+        for roughness in [0.1, 0.3, 0.6, 0.9] {
+            let distribution = TrowbridgeReitz::from_roughness(roughness, roughness);
+            let integral: Real = integrate_pdf(&distribution, &wo).iter().sum();
+            let expected = 1.0 / (1.0 + distribution.alpha_x.powi(2));
+            assert!(
+                (integral - expected).abs() < 0.005,
+                "{roughness}: {integral}"
+            );
+
+            // and the sampling loses as many
+            let sampled: Real = sample_histogram(&distribution, &wo, 100_000).iter().sum();
+            assert!((integral - sampled).abs() < 0.005, "{roughness}: {sampled}");
+        }
+
+        let distribution = TrowbridgeReitz::from_roughness(0.2, 0.5);
+        let integral: Real = integrate_pdf(&distribution, &wo).iter().sum();
+        let lowest = 1.0 / (1.0 + distribution.alpha_y.powi(2));
+        let highest = 1.0 / (1.0 + distribution.alpha_x.powi(2));
+        assert!(integral > lowest - 0.005 && integral < highest + 0.005);
+
+        // and whatever the angle, the rest can't add up to more than one
+        let wo = direction(1.3, 0.4);
+        let integral: Real = integrate_pdf(&distribution, &wo).iter().sum();
+        assert!(integral > 0.5 && integral < 1.0 + 0.005, "{integral}");
+    }
+
+    #[test]
+    fn test_reflection_pdf_matches_sampling() {
+        let distributions = [
+            TrowbridgeReitz::from_roughness(0.4, 0.4),
+            TrowbridgeReitz::from_roughness(0.3, 0.7),
+        ];
+        let outgoing = [
+            direction(0.0, 0.0),
+            direction(0.7, 1.0),
+            direction(1.3, 4.0),
+        ];
+        for distribution in &distributions {
+            for wo in &outgoing {
+                let expected = integrate_pdf(distribution, wo);
+                let sampled = sample_histogram(distribution, wo, 200_000);
+                for (index, (expected, sampled)) in expected.iter().zip(&sampled).enumerate() {
+                    assert!(
+                        (expected - sampled).abs() < 0.005,
+                        "{distribution:?}, {wo:?}, bin {index}: {expected} vs {sampled}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,12 +1,27 @@
 mod aabb;
+pub(crate) mod aov;
 pub(crate) mod bvh;
 pub(crate) mod camera;
 pub(crate) mod color;
+pub(crate) mod denoiser;
+pub(crate) mod environment;
+pub(crate) mod filter;
+pub(crate) mod framebuffer;
 pub(crate) mod hittables;
+pub(crate) mod interrupt;
+pub(crate) mod lights;
 pub(crate) mod materials;
 pub(crate) mod math;
+pub(crate) mod microfacet;
+pub(crate) mod output;
+pub(crate) mod pdf;
+pub(crate) mod projection;
+pub(crate) mod sampler;
 pub(crate) mod shapes;
+pub(crate) mod shutter;
+pub(crate) mod sky;
 pub(crate) mod textures;
+pub(crate) mod tone_mapping;
 pub(crate) mod transform;
 
 pub(crate) use camera::Camera;
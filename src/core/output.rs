@@ -0,0 +1,214 @@
+use crate::core::aov::Pass;
+use crate::core::framebuffer::Framebuffer;
+use crate::core::math::Real;
+use crate::core::tone_mapping::ToneMapper;
+use crate::core::Color;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+use image::{ImageFormat, Rgb32FImage, RgbImage};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// The image format of the rendered output, determined by the extension of the output file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OutputFormat {
+    Png,
+    Jpeg,
+
+    /// Binary (P6) PPM
+    Ppm,
+
+    /// OpenEXR. Stores the linear floating-point values, so nothing above 1.0 gets clipped.
+    Exr,
+
+    /// Radiance RGBE. Like [`Self::Exr`], this keeps the linear HDR values.
+    Hdr,
+}
+
+impl OutputFormat {
+    pub(crate) const JPEG_QUALITY: u8 = 95;
+
+    pub(crate) fn from_path(path: &Path) -> io::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+
+        match extension.as_deref() {
+            Some("png") => Ok(Self::Png),
+            Some("jpg") | Some("jpeg") => Ok(Self::Jpeg),
+            Some("ppm") => Ok(Self::Ppm),
+            Some("exr") => Ok(Self::Exr),
+            Some("hdr") => Ok(Self::Hdr),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Unsupported output format: {}. Supported extensions: png, jpg, jpeg, ppm, exr, hdr",
+                    path.display()
+                ),
+            )),
+        }
+    }
+
+    /// Whether the format stores linear floating-point values instead of gamma-encoded bytes.
+    pub(crate) fn is_hdr(&self) -> bool {
+        matches!(self, Self::Exr | Self::Hdr)
+    }
+}
+
+/// Writes the averaged pixels of `framebuffer` to `path` using `format`. Floating-point formats
+/// only get the exposure applied, while the rest are tone mapped before getting quantized.
+pub(crate) fn write_image(
+    path: &Path,
+    format: OutputFormat,
+    framebuffer: &Framebuffer,
+    tone_mapper: &ToneMapper,
+) -> io::Result<()> {
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    let mut pixels = framebuffer.pixels();
+
+    // marks the pixels of an interrupted render that never got a sample
+    for j in 0..height {
+        for i in 0..width {
+            if framebuffer.sample_count(i, j) == 0 {
+                pixels[(j * width + i) as usize] = placeholder(i, j);
+            }
+        }
+    }
+
+    if format.is_hdr() {
+        let image = Rgb32FImage::from_fn(width, height, |x, y| {
+            image::Rgb(tone_mapper.expose(&pixels[(y * width + x) as usize]).to_f32_array())
+        });
+        write_hdr_image(path, format, &image)
+    } else {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb(tone_mapper.apply(&pixels[(y * width + x) as usize]).to_bytes())
+        });
+        write_ldr_image(path, format, &image)
+    }
+}
+
+/// Where a variant of the image written to `path` gets written, with `name` before the
+/// extension: `image.png` has its depth pass in `image.depth.png`, for example.
+pub(crate) fn sibling_path(path: &Path, name: &str) -> PathBuf {
+    let mut file_name = path.file_stem().unwrap_or_default().to_owned();
+    file_name.push(".");
+    file_name.push(name);
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
+}
+
+/// Writes the averaged samples of `pass`. The lighting passes are written like the beauty image.
+/// Floating-point formats store the data of the other passes as is, while the rest normalize
+/// them: the normals are mapped to [0, 1], the depth is divided by its maximum, and only the
+/// albedo gets the sRGB encoding.
+pub(crate) fn write_pass(
+    path: &Path,
+    format: OutputFormat,
+    pass: Pass,
+    framebuffer: &Framebuffer,
+    tone_mapper: &ToneMapper,
+) -> io::Result<()> {
+    if pass.is_lighting() {
+        return write_image(path, format, framebuffer, tone_mapper);
+    }
+
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    let pixels = framebuffer.pixels();
+    let pixel = |x: u32, y: u32| &pixels[(y * width + x) as usize];
+
+    if format.is_hdr() {
+        let image =
+            Rgb32FImage::from_fn(width, height, |x, y| image::Rgb(pixel(x, y).to_f32_array()));
+        return write_hdr_image(path, format, &image);
+    }
+
+    let max_depth = pixels
+        .iter()
+        .map(|pixel| pixel.red_component())
+        .fold(0.0, Real::max);
+    let image = RgbImage::from_fn(width, height, |x, y| {
+        let pixel = pixel(x, y);
+        image::Rgb(match pass {
+            Pass::Albedo => pixel.to_bytes(),
+            Pass::Normal => quantize(&((pixel + &Color::white()) * 0.5)),
+            Pass::Depth if max_depth > 0.0 => quantize(&(pixel / max_depth)),
+            _ => quantize(pixel),
+        })
+    });
+    write_ldr_image(path, format, &image)
+}
+
+/// Maps each component from [0, 1] to a byte, without any transfer curve.
+fn quantize(color: &Color) -> [u8; 3] {
+    let to_byte = |component: Real| (component.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [
+        to_byte(color.red_component()),
+        to_byte(color.green_component()),
+        to_byte(color.blue_component()),
+    ]
+}
+
+/// Writes the number of samples taken by each pixel of `framebuffer` as a grayscale image.
+/// Floating-point formats store the counts themselves, while the rest scale them so the pixels
+/// with the most samples are white.
+pub(crate) fn write_sample_counts(path: &Path, framebuffer: &Framebuffer) -> io::Result<()> {
+    let format = OutputFormat::from_path(path)?;
+    let count = |x, y| framebuffer.sample_count(x, y) as f32;
+
+    if format.is_hdr() {
+        let image = Rgb32FImage::from_fn(framebuffer.width(), framebuffer.height(), |x, y| {
+            image::Rgb([count(x, y); 3])
+        });
+        write_hdr_image(path, format, &image)
+    } else {
+        let max_count = framebuffer.max_sample_count().max(1) as f32;
+        let image = RgbImage::from_fn(framebuffer.width(), framebuffer.height(), |x, y| {
+            image::Rgb([(count(x, y) / max_count * 255.0).round() as u8; 3])
+        });
+        write_ldr_image(path, format, &image)
+    }
+}
+
+/// A magenta and black checkerboard, which is unlikely to be mistaken for a part of the scene.
+fn placeholder(i: u32, j: u32) -> Color {
+    const SQUARE_SIZE: u32 = 8;
+    if (i / SQUARE_SIZE + j / SQUARE_SIZE).is_multiple_of(2) {
+        Color::new(1.0, 0.0, 1.0)
+    } else {
+        Color::black()
+    }
+}
+
+fn write_hdr_image(path: &Path, format: OutputFormat, image: &Rgb32FImage) -> io::Result<()> {
+    let image_format = match format {
+        OutputFormat::Exr => ImageFormat::OpenExr,
+        _ => ImageFormat::Hdr,
+    };
+    image
+        .save_with_format(path, image_format)
+        .map_err(io::Error::other)
+}
+
+fn write_ldr_image(path: &Path, format: OutputFormat, image: &RgbImage) -> io::Result<()> {
+    match format {
+        OutputFormat::Ppm => {
+            let writer = BufWriter::new(File::create(path)?);
+            let encoder =
+                PnmEncoder::new(writer).with_subtype(PnmSubtype::Pixmap(SampleEncoding::Binary));
+            image.write_with_encoder(encoder)
+        }
+        OutputFormat::Jpeg => {
+            let writer = BufWriter::new(File::create(path)?);
+            let encoder = JpegEncoder::new_with_quality(writer, OutputFormat::JPEG_QUALITY);
+            image.write_with_encoder(encoder)
+        }
+        _ => image.save_with_format(path, ImageFormat::Png),
+    }
+    .map_err(io::Error::other)
+}
@@ -0,0 +1,97 @@
+use crate::core::HittableList;
+use crate::core::math::onb::Onb;
+use crate::core::math::vector::UnitVec3D;
+use crate::core::math::{self, Point, Real, Vec3D};
+use crate::core::microfacet::TrowbridgeReitz;
+
+/// Probability density functions over directions. Each one can generate random directions
+/// and report how likely it is to generate a given direction, so the sampling strategies
+/// of materials and lights can be mixed and weighted against each other.
+#[derive(Clone, Debug)]
+pub(crate) enum Pdf<'a> {
+    /// Uniform over the unit sphere
+    Sphere,
+
+    /// Proportional to the cosine of the angle from the basis' `w` axis
+    Cosine(Onb),
+
+    /// Towards the objects in the list, as seen from `origin`
+    Hittable {
+        objects: &'a HittableList,
+        origin: Point,
+        time: Real,
+    },
+
+    /// Towards the reflections of `outgoing` about the visible normals of a rough surface.
+    /// `outgoing` is in the basis built around the normal.
+    Microfacet {
+        onb: Onb,
+        outgoing: Vec3D,
+        distribution: TrowbridgeReitz,
+    },
+}
+
+impl<'a> Pdf<'a> {
+    pub(crate) fn cosine(normal: &Vec3D) -> Self {
+        Self::Cosine(Onb::new(normal))
+    }
+
+    pub(crate) fn hittable(objects: &'a HittableList, origin: Point, time: Real) -> Self {
+        Self::Hittable {
+            objects,
+            origin,
+            time,
+        }
+    }
+
+    /// For the rays arriving along `direction` at a surface whose tangents and normal are the
+    /// axes of `onb`
+    pub(crate) fn microfacet(onb: Onb, direction: &Vec3D, distribution: TrowbridgeReitz) -> Self {
+        let outgoing = onb.to_local(&-direction.to_unit().0);
+        Self::Microfacet {
+            onb,
+            outgoing,
+            distribution,
+        }
+    }
+
+    pub(crate) fn value(&self, direction: &Vec3D) -> Real {
+        match self {
+            Self::Sphere => 1.0 / (4.0 * math::PI),
+            Self::Cosine(onb) => {
+                let cosine_theta = onb.w().dot(&direction.to_unit().0);
+                (cosine_theta / math::PI).max(0.0)
+            }
+            Self::Hittable {
+                objects,
+                origin,
+                time,
+            } => objects.pdf_value(origin, direction, *time),
+            Self::Microfacet {
+                onb,
+                outgoing,
+                distribution,
+            } => distribution.reflection_pdf(outgoing, &onb.to_local(&direction.to_unit().0)),
+        }
+    }
+
+    pub(crate) fn generate(&self) -> Vec3D {
+        match self {
+            Self::Sphere => Vec3D::random_unit().0,
+            Self::Cosine(onb) => onb.transform(&Vec3D::random_cosine_direction()),
+            Self::Hittable {
+                objects,
+                origin,
+                time,
+            } => objects.random_direction(origin, *time),
+            Self::Microfacet {
+                onb,
+                outgoing,
+                distribution,
+            } => {
+                let half = distribution.sample_visible_normal(outgoing);
+                onb.transform(&(-outgoing).reflect(&UnitVec3D(half)))
+            }
+        }
+    }
+}
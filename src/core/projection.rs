@@ -0,0 +1,86 @@
+use crate::core::math::vector::Vec3D;
+use crate::core::math::{self, Real};
+use serde::Deserialize;
+
+/// How the camera maps the points of the image to rays.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Projection {
+    /// A pinhole, or a thin lens with depth of field. `field_of_view` is the vertical angle.
+    #[default]
+    Perspective,
+
+    /// Parallel rays, through a viewport of `orthographic_height` world units. Parallel lines
+    /// stay parallel, whatever the distance.
+    Orthographic,
+
+    /// A fisheye lens where the distance from the center of the image is proportional to the
+    /// angle from the viewing direction. `field_of_view` is the vertical angle, and can go up to
+    /// 360 degrees.
+    FisheyeEquidistant,
+
+    /// A fisheye lens that preserves the areas, so every pixel covers the same solid angle.
+    /// `field_of_view` is the vertical angle, and can go up to 360 degrees.
+    FisheyeEquisolid,
+
+    /// The whole sphere around the camera, with the longitude along the width of the image and
+    /// the latitude along its height. Best with an aspect ratio of 2.
+    Equirectangular,
+}
+
+impl Projection {
+    /// The direction through the point `u`, `v` of the image, both in [0, 1] from its upper-left
+    /// corner, in the camera space where x points right, y up and z forward. `None` for the
+    /// points outside the image circle of a fisheye lens. Only for the fisheye and
+    /// equirectangular projections, which don't use a viewport.
+    pub(crate) fn direction(
+        &self,
+        u: Real,
+        v: Real,
+        field_of_view: Real,
+        aspect_ratio: Real,
+    ) -> Option<Vec3D> {
+        match self {
+            Self::Perspective | Self::Orthographic => None,
+            Self::FisheyeEquidistant | Self::FisheyeEquisolid => {
+                // the height of the image spans [-1, 1]
+                let x = (2.0 * u - 1.0) * aspect_ratio;
+                let y = 1.0 - 2.0 * v;
+                let radius = (x * x + y * y).sqrt();
+
+                let half_angle = math::degrees_to_radians(field_of_view) / 2.0;
+                let theta = if *self == Self::FisheyeEquidistant {
+                    radius * half_angle
+                } else {
+                    let sin_half_theta = radius * (half_angle / 2.0).sin();
+                    if sin_half_theta > 1.0 {
+                        return None;
+                    }
+                    2.0 * sin_half_theta.asin()
+                };
+                if theta > math::PI {
+                    return None;
+                }
+
+                if radius == 0.0 {
+                    return Some(Vec3D::new(0.0, 0.0, 1.0));
+                }
+                let sin_theta = theta.sin();
+                Some(Vec3D::new(
+                    sin_theta * x / radius,
+                    sin_theta * y / radius,
+                    theta.cos(),
+                ))
+            }
+            Self::Equirectangular => {
+                let longitude = (2.0 * u - 1.0) * math::PI;
+                let latitude = (1.0 - 2.0 * v) * math::PI / 2.0;
+                Some(Vec3D::new(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    latitude.cos() * longitude.cos(),
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use crate::core::math::{self, Real};
+use serde::Deserialize;
+use std::cell::RefCell;
+
+/// How the random numbers of each sample are generated. The camera asks for the pixel, lens and
+/// time dimensions first, and the bounces of the path consume the rest, in pairs.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Sampler {
+    /// Uniform random numbers, unrelated between samples
+    #[default]
+    Independent,
+
+    /// Jittered samples, one in each cell of a `sqrt(spp)` x `sqrt(spp)` grid for every pair
+    /// of dimensions
+    Stratified,
+
+    /// The Halton sequence, with the digits randomly permuted per pixel. Dimensions past the
+    /// available prime bases fall back to independent numbers.
+    Halton,
+
+    /// The first two dimensions of the Sobol sequence, shuffled and Owen-scrambled independently
+    /// for every pair of dimensions
+    Sobol,
+}
+
+/// The state of the sample being taken on the current thread
+struct SampleStream {
+    sampler: Sampler,
+    seed: u64,
+    index: u32,
+
+    /// The number of samples of the pixel, which sets the resolution of the stratification
+    sample_count: u32,
+
+    /// The next pair of dimensions to generate
+    pair: u32,
+
+    /// The second coordinate of the last pair, if it hasn't been used yet
+    pending: Option<Real>,
+}
+
+thread_local! {
+    static STREAM: RefCell<Option<SampleStream>> = const { RefCell::new(None) };
+}
+
+const ONE_MINUS_EPSILON: Real = 1.0 - Real::EPSILON / 2.0;
+
+/// The smallest difference between two samples, as they are generated from 32 bits
+const RESOLUTION: Real = 1.0 / (1u64 << 32) as Real;
+
+/// The prime bases of the Halton dimensions
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+impl Sampler {
+    /// Makes the random numbers of the current thread come from the sample `index` of a pixel,
+    /// until [`Self::end_sample`]. `seed` identifies the pixel.
+    pub(crate) fn start_sample(&self, seed: u64, index: u32, sample_count: u32) {
+        STREAM.with(|stream| {
+            *stream.borrow_mut() = Some(SampleStream {
+                sampler: *self,
+                seed,
+                index,
+                sample_count: sample_count.max(1),
+                pair: 0,
+                pending: None,
+            })
+        });
+    }
+
+    pub(crate) fn end_sample() {
+        STREAM.with(|stream| *stream.borrow_mut() = None);
+    }
+
+    /// The next dimension of the current sample, or `None` if the numbers should be independent
+    pub(crate) fn next_1d() -> Option<Real> {
+        STREAM.with(|stream| {
+            let mut stream = stream.borrow_mut();
+            let stream = stream.as_mut()?;
+            match stream.pending.take() {
+                Some(value) => Some(value),
+                None => {
+                    let (x, y) = stream.next_pair()?;
+                    stream.pending = Some(y);
+                    Some(x)
+                }
+            }
+        })
+    }
+
+    /// The next two dimensions of the current sample, which are stratified together.
+    pub(crate) fn next_2d() -> Option<(Real, Real)> {
+        STREAM.with(|stream| {
+            let mut stream = stream.borrow_mut();
+            let stream = stream.as_mut()?;
+            stream.pending = None;
+            stream.next_pair()
+        })
+    }
+}
+
+impl SampleStream {
+    fn next_pair(&mut self) -> Option<(Real, Real)> {
+        let pair = self.pair;
+        self.pair += 1;
+        let seed = math::mix_seed(self.seed, pair as u64);
+
+        match self.sampler {
+            Sampler::Independent => None,
+            Sampler::Stratified => Some(self.stratified(seed)),
+            Sampler::Halton => {
+                let dimension = 2 * pair as usize;
+                let bases = PRIMES.get(dimension..dimension + 2)?;
+                Some((
+                    permuted_radical_inverse(bases[0], self.index, seed),
+                    permuted_radical_inverse(bases[1], self.index, math::mix_seed(seed, 1)),
+                ))
+            }
+            Sampler::Sobol => Some(self.sobol(seed)),
+        }
+    }
+
+    fn stratified(&self, seed: u64) -> (Real, Real) {
+        let resolution = (self.sample_count as Real).sqrt() as u32;
+        let cells = resolution * resolution;
+
+        // the samples past the grid start a new round, with its own order of the cells
+        let round = self.index / cells;
+        let seed = math::mix_seed(seed, round as u64);
+        let cell = permute(self.index % cells, cells, seed as u32);
+
+        let coordinate = |cell: u32, jitter_seed: u64| {
+            let jitter = to_unit_interval(math::mix_seed(seed, jitter_seed) as u32);
+            ((cell as Real + jitter) / resolution as Real).min(ONE_MINUS_EPSILON)
+        };
+        (
+            coordinate(cell % resolution, 1),
+            coordinate(cell / resolution, 2),
+        )
+    }
+
+    /// Burley's shuffled and scrambled Sobol (0, 2)-sequence
+    fn sobol(&self, seed: u64) -> (Real, Real) {
+        let index = nested_uniform_scramble(self.index, seed as u32);
+        let x = nested_uniform_scramble(index.reverse_bits(), math::mix_seed(seed, 1) as u32);
+        let y = nested_uniform_scramble(
+            sobol_second_dimension(index),
+            math::mix_seed(seed, 2) as u32,
+        );
+        (to_unit_interval(x), to_unit_interval(y))
+    }
+}
+
+fn to_unit_interval(bits: u32) -> Real {
+    bits as Real * RESOLUTION
+}
+
+/// The second dimension of the Sobol sequence, whose generator matrix is Pascal's triangle
+/// modulo 2. The bits of the result are in the order of a fraction, most significant first.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut direction = 1 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+/// Owen scrambling, which randomly flips the bits of `x` depending on the bits before them
+/// (Laine-Karras hash with Burley's constants).
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits().wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+/// The radical inverse of `index` in `base`, with the digits at each position shuffled by
+/// their own random permutation.
+fn permuted_radical_inverse(base: u32, mut index: u32, seed: u64) -> Real {
+    let inverse_base = 1.0 / base as Real;
+    let mut scale = inverse_base;
+    let mut result = 0.0;
+
+    // all the digits down to the resolution of the other samplers get permuted, including the
+    // leading zeros
+    let mut position = 0;
+    while scale > RESOLUTION {
+        let digit = index % base;
+        let seed = math::mix_seed(seed, position);
+        result += permute(digit, base, seed as u32) as Real * scale;
+
+        index /= base;
+        scale *= inverse_base;
+        position += 1;
+    }
+    result.min(ONE_MINUS_EPSILON)
+}
+
+/// A random permutation of `0..length`, evaluated at `i` without building it
+/// (Kensler's hash from "Correlated Multi-Jittered Sampling").
+pub(crate) fn permute(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= mask;
+        i ^= i >> 5;
+
+        // cycles until the value falls back in the range
+        if i < length {
+            return ((i as u64 + seed as u64) % length as u64) as u32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sampler;
+    use crate::core::math::Real;
+
+    /// The first `pairs` pairs of dimensions of the samples of a pixel
+    fn samples(
+        sampler: Sampler,
+        seed: u64,
+        sample_count: u32,
+        pairs: usize,
+    ) -> Vec<Vec<(Real, Real)>> {
+        (0..sample_count)
+            .map(|index| {
+                sampler.start_sample(seed, index, sample_count);
+                let pairs = (0..pairs).map(|_| Sampler::next_2d().unwrap()).collect();
+                Sampler::end_sample();
+                pairs
+            })
+            .collect()
+    }
+
+    /// Whether every cell of a `columns` x `rows` grid holds exactly one of the points
+    fn is_stratified(
+        points: impl Iterator<Item = (Real, Real)>,
+        columns: usize,
+        rows: usize,
+    ) -> bool {
+        let mut counts = vec![0; columns * rows];
+        for (x, y) in points {
+            let column = (x * columns as Real) as usize;
+            let row = (y * rows as Real) as usize;
+            counts[row * columns + column] += 1;
+        }
+        counts.iter().all(|&count| count == 1)
+    }
+
+    #[test]
+    fn test_samples_in_unit_square() {
+        for sampler in [Sampler::Stratified, Sampler::Halton, Sampler::Sobol] {
+            for seed in 0..16 {
+                for pairs in samples(sampler, seed, 50, 8) {
+                    assert!(
+                        pairs
+                            .iter()
+                            .all(|&(x, y)| (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y))
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_independent_defers_to_rng() {
+        Sampler::Independent.start_sample(0, 0, 16);
+        assert!(Sampler::next_2d().is_none());
+        assert!(Sampler::next_1d().is_none());
+        Sampler::end_sample();
+    }
+
+    #[test]
+    fn test_stratification() {
+        // the Sobol points are a (0, 2)-net, so any grid of 64 cells would do
+        for (sampler, columns, rows, sample_count) in [
+            (Sampler::Stratified, 8, 8, 64),
+            (Sampler::Sobol, 8, 8, 64),
+            (Sampler::Sobol, 4, 16, 64),
+            (Sampler::Halton, 4, 9, 36),
+        ] {
+            for seed in 0..4 {
+                let samples = samples(sampler, seed, sample_count, 1);
+                assert!(
+                    is_stratified(samples.iter().map(|pairs| pairs[0]), columns, rows),
+                    "{sampler:?} with seed {seed}"
+                );
+            }
+        }
+
+        // every pair of dimensions of the stratified sampler has its own order of the cells
+        let samples = samples(Sampler::Stratified, 0, 16, 4);
+        for pair in 0..4 {
+            assert!(is_stratified(samples.iter().map(|pairs| pairs[pair]), 4, 4));
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use crate::core::aabb::AABB;
+use crate::core::bvh::{self, BVHBuilder, BVHNode, SplitStrategy};
+use crate::core::hittables::{FrontFace, HitPoint, HitRecord, Mat, Normal, T, U, V};
+use crate::core::materials::{
+    refractive_index, Dielectric, DiffuseLight, Lambertian, MaterialKind, Metal,
+};
+use crate::core::math::interval::Interval;
+use crate::core::math::vector::UnitVec3D;
+use crate::core::math::{Point, Real, Vec3D};
+use crate::core::shapes::planars::Planar;
+use crate::core::textures::{ImageTexture, Texture};
+use crate::core::{math, Color, Material, Ray};
+use std::path::Path;
+use std::sync::Arc;
+
+/// An indexed triangle mesh. The vertex attributes live in buffers shared by all the triangles,
+/// and the triangles are organized by their own BVH, so the whole mesh is a single [`Hittable`].
+/// Cloning a mesh only clones a reference to its data.
+///
+/// [`Hittable`]: crate::core::Hittable
+#[derive(Clone, Debug)]
+pub(crate) struct Mesh {
+    data: Arc<MeshData>,
+}
+
+#[derive(Debug)]
+struct MeshData {
+    positions: Vec<Point>,
+
+    /// Per-vertex normals. Vertices without normals have zero vectors here.
+    normals: Vec<Vec3D>,
+
+    /// Per-vertex texture coordinates. Vertices without them have `(0, 0)` here.
+    uvs: Vec<(Real, Real)>,
+
+    triangles: Vec<MeshTriangle>,
+    materials: Vec<Material>,
+    nodes: Vec<BVHNode>,
+    bbox: AABB,
+}
+
+#[derive(Clone, Debug)]
+struct MeshTriangle {
+    vertices: [u32; 3],
+    material: u32,
+
+    /// Whether the normals of the vertices should be interpolated (smooth shading)
+    smooth: bool,
+
+    /// Whether the texture coordinates of the vertices should be interpolated. If not,
+    /// the barycentric coordinates are used instead.
+    textured: bool,
+}
+
+impl Mesh {
+    pub(crate) const MAX_TRIANGLES_PER_LEAF: usize = 4;
+
+    /// Loads a Wavefront OBJ file, together with the materials of its MTL files.
+    /// `default_material` is used by the faces that don't have a material of their own.
+    pub(crate) fn from_obj(
+        path: &Path,
+        default_material: Material,
+    ) -> Result<Self, tobj::LoadError> {
+        let (models, obj_materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+        let directory = path.parent().unwrap_or(Path::new("."));
+
+        let mut materials = vec![default_material];
+        match obj_materials {
+            Ok(obj_materials) => materials.extend(
+                obj_materials
+                    .iter()
+                    .map(|material| Self::convert_material(material, directory)),
+            ),
+            Err(err) => log::warn!("Unable to load the materials of {}: {err}", path.display()),
+        }
+
+        let mut positions = vec![];
+        let mut normals = vec![];
+        let mut uvs = vec![];
+        let mut triangles = vec![];
+
+        for model in models {
+            let mesh = &model.mesh;
+            let offset = positions.len() as u32;
+            let vertex_count = mesh.positions.len() / 3;
+            let smooth = mesh.normals.len() == mesh.positions.len();
+            let textured = mesh.texcoords.len() / 2 == vertex_count;
+
+            for i in 0..vertex_count {
+                positions.push(Point::new(
+                    mesh.positions[3 * i] as Real,
+                    mesh.positions[3 * i + 1] as Real,
+                    mesh.positions[3 * i + 2] as Real,
+                ));
+                normals.push(if smooth {
+                    Vec3D::new(
+                        mesh.normals[3 * i] as Real,
+                        mesh.normals[3 * i + 1] as Real,
+                        mesh.normals[3 * i + 2] as Real,
+                    )
+                } else {
+                    Vec3D::zero()
+                });
+                uvs.push(if textured {
+                    (
+                        mesh.texcoords[2 * i] as Real,
+                        mesh.texcoords[2 * i + 1] as Real,
+                    )
+                } else {
+                    (0.0, 0.0)
+                });
+            }
+
+            // index 0 is the default material
+            let material = mesh.material_id.map_or(0, |id| id + 1);
+            let material = if material < materials.len() {
+                material
+            } else {
+                0
+            };
+
+            for face in mesh.indices.chunks_exact(3) {
+                triangles.push(MeshTriangle {
+                    vertices: [face[0] + offset, face[1] + offset, face[2] + offset],
+                    material: material as u32,
+                    smooth,
+                    textured,
+                });
+            }
+        }
+
+        log::info!(
+            "Loaded {} with {} vertices and {} triangles.",
+            path.display(),
+            positions.len(),
+            triangles.len()
+        );
+
+        Ok(Self::new(positions, normals, uvs, triangles, materials))
+    }
+
+    fn new(
+        positions: Vec<Point>,
+        normals: Vec<Vec3D>,
+        uvs: Vec<(Real, Real)>,
+        triangles: Vec<MeshTriangle>,
+        materials: Vec<Material>,
+    ) -> Self {
+        let mut data = MeshData {
+            positions,
+            normals,
+            uvs,
+            triangles,
+            materials,
+            nodes: vec![],
+            bbox: AABB::empty(),
+        };
+
+        let boxes = data
+            .triangles
+            .iter()
+            .map(|triangle| data.triangle_bounding_box(triangle))
+            .collect::<Vec<AABB>>();
+        let (nodes, order) =
+            BVHBuilder::new(SplitStrategy::Sah, Self::MAX_TRIANGLES_PER_LEAF).build(&boxes);
+        data.triangles = order
+            .into_iter()
+            .map(|i| data.triangles[i].clone())
+            .collect();
+        data.bbox = boxes.iter().fold(AABB::empty(), |bbox, triangle_box| {
+            AABB::from_boxes(&bbox, triangle_box)
+        });
+        data.nodes = nodes;
+
+        Self {
+            data: Arc::new(data),
+        }
+    }
+
+    /// Maps the MTL parameters to the closest material we support. The illumination models for
+    /// glass become dielectrics, the ones for mirrors become metals, and everything else is
+    /// Lambertian. Faces with an emission (`Ke`) become lights.
+    fn convert_material(material: &tobj::Material, directory: &Path) -> Material {
+        let to_color = |rgb: [f32; 3]| Color::new(rgb[0] as Real, rgb[1] as Real, rgb[2] as Real);
+
+        let emission = material.unknown_param.get("Ke").and_then(|value| {
+            let rgb = value
+                .split_whitespace()
+                .map(|component| component.parse::<Real>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            match rgb[..] {
+                [r, g, b] if r > 0.0 || g > 0.0 || b > 0.0 => Some(Color::new(r, g, b)),
+                _ => None,
+            }
+        });
+        if let Some(emission) = emission {
+            return Material::new(MaterialKind::DiffuseLight(DiffuseLight::from_emission(
+                emission,
+            )));
+        }
+
+        match material.illumination_model {
+            Some(4) | Some(6) | Some(7) => {
+                Material::new(MaterialKind::Dielectric(Dielectric::new(
+                    material
+                        .optical_density
+                        .map_or(refractive_index::GLASS, |ior| ior as Real),
+                )))
+            }
+            Some(3) | Some(5) => {
+                let albedo = material.specular.map_or(Color::white(), to_color);
+
+                // maps the Phong exponent to a roughness
+                let fuzz = material
+                    .shininess
+                    .map_or(0.0, |shininess| (2.0 / (shininess as Real + 2.0)).sqrt());
+                Material::new(MaterialKind::Metal(Metal::new(albedo, fuzz)))
+            }
+            _ => {
+                let texture = material.diffuse_texture.as_ref().and_then(|texture| {
+                    let texture_path = directory.join(texture);
+                    ImageTexture::from_path(&texture_path.to_string_lossy())
+                        .map_err(|err| {
+                            log::warn!("Unable to load texture {}: {err}", texture_path.display())
+                        })
+                        .ok()
+                });
+                match texture {
+                    Some(texture) => Material::new(MaterialKind::Lambertian(
+                        Lambertian::from_texture(Texture::Image(texture)),
+                    )),
+                    None => Material::new(MaterialKind::Lambertian(Lambertian::from_albedo(
+                        material.diffuse.map_or(Color::new(0.8, 0.8, 0.8), to_color),
+                    ))),
+                }
+            }
+        }
+    }
+
+    pub(crate) fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
+        let data = &self.data;
+        bvh::hit_nodes(&data.nodes, ray, ray_t, |i, ray_t| {
+            data.hit_triangle(&data.triangles[i], ray, ray_t)
+        })
+    }
+
+    pub(crate) fn bounding_box(&self) -> &AABB {
+        &self.data.bbox
+    }
+
+    pub(crate) fn materials(&self) -> &[Material] {
+        &self.data.materials
+    }
+
+    /// The triangles with an emissive material, as shapes of their own so they can be sampled
+    /// as lights
+    pub(crate) fn emissive_triangles(&self) -> impl Iterator<Item = Planar> + '_ {
+        let data = &self.data;
+        data.triangles
+            .iter()
+            .filter(|triangle| data.materials[triangle.material as usize].is_emissive())
+            .map(|triangle| {
+                let [p0, p1, p2] = triangle.vertices.map(|i| &data.positions[i as usize]);
+                Planar::triangle(
+                    p0.clone(),
+                    p1 - p0,
+                    p2 - p0,
+                    data.materials[triangle.material as usize].clone(),
+                )
+            })
+    }
+}
+
+impl MeshData {
+    /// Möller–Trumbore ray-triangle intersection
+    fn hit_triangle(
+        &self,
+        triangle: &MeshTriangle,
+        ray: &Ray,
+        ray_t: &Interval,
+    ) -> Option<HitRecord<'_>> {
+        let [i0, i1, i2] = triangle.vertices.map(|i| i as usize);
+        let p0 = &self.positions[i0];
+        let edge1 = &self.positions[i1] - p0;
+        let edge2 = &self.positions[i2] - p0;
+
+        let p = ray.direction().cross(&edge2);
+        let determinant = edge1.dot(&p);
+
+        // the ray is parallel to the triangle
+        if math::near_zero(determinant.abs()) {
+            return None;
+        }
+
+        let inverse_determinant = 1.0 / determinant;
+        let s = ray.origin() - p0;
+        let b1 = s.dot(&p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let q = s.cross(&edge1);
+        let b2 = ray.direction().dot(&q) * inverse_determinant;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(&q) * inverse_determinant;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        let (front_face, face_normal) = HitRecord::face_normal(ray, edge1.cross(&edge2).to_unit());
+
+        let normal = if triangle.smooth {
+            let shading_normal =
+                (&self.normals[i0] * b0 + &self.normals[i1] * b1 + &self.normals[i2] * b2)
+                    .to_unit();
+
+            // keeps the shading normal on the same side as the geometric one
+            if shading_normal.dot(&face_normal.0) < 0.0 {
+                UnitVec3D(-shading_normal.0)
+            } else {
+                shading_normal
+            }
+        } else {
+            face_normal
+        };
+
+        let (u, v, tangent) = if triangle.textured {
+            let (u0, v0) = self.uvs[i0];
+            let (u1, v1) = self.uvs[i1];
+            let (u2, v2) = self.uvs[i2];
+
+            // solves edge1 = (u1 - u0) dp/du + (v1 - v0) dp/dv, and the same for edge2
+            let (du1, dv1, du2, dv2) = (u1 - u0, v1 - v0, u2 - u0, v2 - v0);
+            let uv_determinant = du1 * dv2 - dv1 * du2;
+            let tangent = if math::near_zero(uv_determinant.abs()) {
+                edge1.clone()
+            } else {
+                (&(&edge1 * dv2) - &edge2 * dv1) / uv_determinant
+            };
+            (
+                b0 * u0 + b1 * u1 + b2 * u2,
+                b0 * v0 + b1 * v1 + b2 * v2,
+                tangent,
+            )
+        } else {
+            (b1, b2, edge1.clone())
+        };
+
+        Some(
+            HitRecord::new(
+                HitPoint(ray.at(t)),
+                Normal(normal),
+                Mat(&self.materials[triangle.material as usize]),
+                T(t),
+                FrontFace(front_face),
+                U(u),
+                V(v),
+            )
+            .with_tangent(tangent),
+        )
+    }
+
+    fn triangle_bounding_box(&self, triangle: &MeshTriangle) -> AABB {
+        let [p0, p1, p2] = triangle
+            .vertices
+            .map(|i| self.positions[i as usize].clone());
+        AABB::from_boxes(
+            &AABB::from_points(p0, p1),
+            &AABB::from_points(p2.clone(), p2),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mesh;
+    use crate::core::materials::{Lambertian, MaterialKind};
+    use crate::core::math::interval::Interval;
+    use crate::core::math::{self, Point, Vec3D};
+    use crate::core::{Color, Hittable, Material, Ray};
+    use std::fs;
+    use std::path::Path;
+
+    fn default_material() -> Material {
+        Material::new(MaterialKind::Lambertian(Lambertian::from_albedo(
+            Color::white(),
+        )))
+    }
+
+    #[test]
+    fn test_load_icosphere() {
+        let mesh = Mesh::from_obj(Path::new("examples/models/icosphere.obj"), default_material())
+            .unwrap();
+
+        assert_eq!(mesh.data.triangles.len(), 320);
+
+        // the default material, then the copper of the MTL file
+        assert_eq!(mesh.materials().len(), 2);
+        assert!(matches!(mesh.materials()[1].kind(), MaterialKind::Metal(_)));
+        assert!(
+            mesh.data
+                .triangles
+                .iter()
+                .all(|triangle| triangle.material == 1 && triangle.smooth)
+        );
+
+        // a unit sphere, give or take the flat faces
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), Vec3D::new(0.0, 0.0, -1.0));
+        let record = mesh
+            .hit(&ray, &Interval::new(0.001, math::INFINITY))
+            .unwrap();
+        assert!((record.t() - 4.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_emissive_triangles_are_lights() {
+        let directory = std::env::temp_dir().join("eanray_test_emissive_mesh");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("lamp.mtl"),
+            "newmtl glow\nKe 4 4 4\nnewmtl matte\nKd 0.5 0.5 0.5\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("lamp.obj"),
+            "mtllib lamp.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\n\
+             usemtl glow\nf 1 2 3\nusemtl matte\nf 2 4 3\n",
+        )
+        .unwrap();
+
+        let mesh = Mesh::from_obj(&directory.join("lamp.obj"), default_material()).unwrap();
+        assert_eq!(mesh.emissive_triangles().count(), 1);
+        assert_eq!(Hittable::Mesh(mesh).lights().objects().len(), 1);
+    }
+}
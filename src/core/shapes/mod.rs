@@ -1,3 +1,4 @@
+pub(crate) mod mesh;
 pub(crate) mod planars;
 pub(crate) mod plane;
 pub(crate) mod quadrics;
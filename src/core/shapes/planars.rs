@@ -85,20 +85,59 @@ impl Planar {
         if self.is_interior(alpha, beta) {
             let (front_face, face_normal) = HitRecord::face_normal(&ray, self.normal.clone());
 
-            Some(HitRecord::new(
-                hittables::HitPoint(intersection),
-                hittables::Normal(face_normal),
-                hittables::Mat(self.fields.material()),
-                hittables::T(t),
-                hittables::FrontFace(front_face),
-                hittables::U(alpha),
-                hittables::V(beta),
-            ))
+            Some(
+                HitRecord::new(
+                    hittables::HitPoint(intersection),
+                    hittables::Normal(face_normal),
+                    hittables::Mat(self.fields.material()),
+                    hittables::T(t),
+                    hittables::FrontFace(front_face),
+                    hittables::U(alpha),
+                    hittables::V(beta),
+                )
+                .with_tangent(self.u.clone()),
+            )
         } else {
             None
         }
     }
 
+    /// The probability density of [`Self::random_direction`] generating `direction` from `origin`.
+    /// Since the points are sampled uniformly by area, the density is converted to solid angle.
+    pub(crate) fn pdf_value(&self, origin: &Point, direction: &Vec3D, time: Real) -> Real {
+        let ray = Ray::new_timed(origin.clone(), direction.clone(), time);
+
+        if let Some(record) = self.hit(&ray, &Interval::new(0.001, math::INFINITY)) {
+            let distance_squared = record.t() * record.t() * direction.length_squared();
+            let cosine = (direction.dot(&record.normal().0) / direction.length()).abs();
+            distance_squared / (cosine * self.area())
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the direction from `origin` to a random point on the surface.
+    pub(crate) fn random_direction(&self, origin: &Point) -> Vec3D {
+        let (a, b) = match &self.kind {
+            Kind::Quad(_) => Quad::random_coordinates(),
+            Kind::Triangle(_) => Triangle::random_coordinates(),
+            Kind::Disk(disk) => disk.random_coordinates(),
+        };
+        let point = &self.q + &self.u * a + &self.v * b;
+        point - origin
+    }
+
+    pub(crate) fn area(&self) -> Real {
+        // the area of the parallelogram formed by `u` and `v`
+        let parallelogram_area = self.u.cross(&self.v).length();
+
+        match &self.kind {
+            Kind::Quad(_) => parallelogram_area,
+            Kind::Triangle(_) => parallelogram_area / 2.0,
+            Kind::Disk(disk) => math::PI * disk.radius * disk.radius * parallelogram_area,
+        }
+    }
+
     fn is_interior(&self, a: Real, b: Real) -> bool {
         match &self.kind {
             Kind::Quad(_) => Quad::is_interior(a, b),
@@ -119,6 +158,10 @@ impl Quad {
 
         unit_interval.contains(a) && unit_interval.contains(b)
     }
+
+    fn random_coordinates() -> (Real, Real) {
+        math::random_pair()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -128,6 +171,16 @@ impl Triangle {
     fn is_interior(a: Real, b: Real) -> bool {
         a > 0.0 && b > 0.0 && a + b < 1.0
     }
+
+    /// Samples the unit square and folds the points outside the triangle back into it.
+    fn random_coordinates() -> (Real, Real) {
+        let (a, b) = math::random_pair();
+        if a + b > 1.0 {
+            (1.0 - a, 1.0 - b)
+        } else {
+            (a, b)
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -139,4 +192,12 @@ impl Disk {
     fn is_interior(&self, a: Real, b: Real) -> bool {
         (a * a + b * b).sqrt() < self.radius
     }
+
+    fn random_coordinates(&self) -> (Real, Real) {
+        // the square root keeps the points uniformly distributed over the area
+        let (r1, r2) = math::random_pair();
+        let r = self.radius * r1.sqrt();
+        let theta = 2.0 * math::PI * r2;
+        (r * theta.cos(), r * theta.sin())
+    }
 }
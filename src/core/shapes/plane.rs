@@ -38,15 +38,18 @@ impl Plane {
                 let (u_vec, v_vec) = self.compute_uv();
                 let delta = &hit_point - &self.p0;
 
-                Some(HitRecord::new(
-                    hittables::HitPoint(hit_point),
-                    hittables::Normal(face_normal),
-                    hittables::Mat(self.fields.material()),
-                    hittables::T(t),
-                    hittables::FrontFace(front_face),
-                    hittables::U(delta.dot(&u_vec)),
-                    hittables::V(delta.dot(&v_vec)),
-                ))
+                Some(
+                    HitRecord::new(
+                        hittables::HitPoint(hit_point),
+                        hittables::Normal(face_normal),
+                        hittables::Mat(self.fields.material()),
+                        hittables::T(t),
+                        hittables::FrontFace(front_face),
+                        hittables::U(delta.dot(&u_vec)),
+                        hittables::V(delta.dot(&v_vec)),
+                    )
+                    .with_tangent(u_vec),
+                )
             } else {
                 None
             }
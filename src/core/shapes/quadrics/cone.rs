@@ -94,6 +94,13 @@ impl Cone {
         )
     }
 
+    pub(crate) fn cap_material(&self) -> Option<&Material> {
+        match &self.end_type {
+            EndType::Closed { cap_mat } => Some(cap_mat),
+            EndType::Open => None,
+        }
+    }
+
     pub fn nearest_cap_hit(&self, ray: &Ray, ray_t: &Interval) -> (Real, HitType) {
         match self.end_type {
             EndType::Closed { .. } => compare_cap_hits(
@@ -226,16 +233,23 @@ where
             HitType::ApexCap => Vec3D::new(0.0, -1.0, 0.0).to_unit(),
         };
         let (u, v) = compute_uv(&outward_normal);
+        let tangent = match hit_type {
+            HitType::Side => Vec3D::new(outward_normal.z, 0.0, -outward_normal.x),
+            _ => Vec3D::new(1.0, 0.0, 0.0),
+        };
         let (front_face, face_normal) = HitRecord::face_normal(&ray, outward_normal);
 
-        Some(HitRecord::new(
-            hittables::HitPoint(p),
-            hittables::Normal(face_normal),
-            hittables::Mat(compute_mat()),
-            hittables::T(t),
-            hittables::FrontFace(front_face),
-            hittables::U(u),
-            hittables::V(v),
-        ))
+        Some(
+            HitRecord::new(
+                hittables::HitPoint(p),
+                hittables::Normal(face_normal),
+                hittables::Mat(compute_mat()),
+                hittables::T(t),
+                hittables::FrontFace(front_face),
+                hittables::U(u),
+                hittables::V(v),
+            )
+            .with_tangent(tangent),
+        )
     }
 }
@@ -136,6 +136,16 @@ impl Cylinder {
         }
     }
 
+    pub(crate) fn cap_material(&self) -> Option<&Material> {
+        match &self.kind {
+            CylinderKind::Finite {
+                kind: FiniteType::Closed { cap_mat },
+                ..
+            } => Some(cap_mat),
+            _ => None,
+        }
+    }
+
     pub fn nearest_cap_hit(&self, ray: &Ray, ray_t: &Interval) -> (Real, HitType) {
         match self.kind {
             CylinderKind::Finite {
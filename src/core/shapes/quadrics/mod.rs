@@ -3,7 +3,7 @@ use crate::core::math::interval::Interval;
 use crate::core::math::Real;
 use crate::core::shapes::quadrics::cone::Cone;
 use crate::core::shapes::Sphere;
-use crate::core::Ray;
+use crate::core::{Material, Ray};
 use cylinder::Cylinder;
 
 pub(crate) mod cone;
@@ -34,6 +34,18 @@ impl Quadric {
         }
     }
 
+    /// The material of the side, followed by the one of the caps if they have their own
+    pub(crate) fn materials(&self) -> Vec<&Material> {
+        let cap_material = match self {
+            Self::Sphere(_) => None,
+            Self::Cylinder(cylinder) => cylinder.cap_material(),
+            Self::Cone(cone) => cone.cap_material(),
+        };
+        std::iter::once(self.fields().material())
+            .chain(cap_material)
+            .collect()
+    }
+
     pub(crate) fn fields_mut(&mut self) -> &mut HittableFields {
         match self {
             Self::Sphere(sphere) => &mut sphere.fields,
@@ -2,6 +2,7 @@ use crate::core::aabb::AABB;
 use crate::core::hittables::{HitRecord, HittableFields};
 use crate::core::materials::Material;
 use crate::core::math::interval::Interval;
+use crate::core::math::onb::Onb;
 use crate::core::math::ray::Ray;
 use crate::core::math::vector::UnitVec3D;
 use crate::core::math::{Point, Real, Vec3D};
@@ -79,6 +80,7 @@ impl Sphere {
                 let p = ray.at(root);
                 let outward_normal = UnitVec3D((&p - current_center) / self.radius);
                 let (u, v) = self.compute_uv(&outward_normal);
+                let tangent = Vec3D::new(outward_normal.z, 0.0, -outward_normal.x);
                 let (front_face, face_normal) = HitRecord::face_normal(&ray, outward_normal);
 
                 HitRecord::new(
@@ -90,10 +92,48 @@ impl Sphere {
                     hittables::U(u),
                     hittables::V(v),
                 )
+                .with_tangent(tangent)
             })
         }
     }
 
+    /// The probability density of [`Self::random_direction`] generating `direction` from `origin`.
+    pub(crate) fn pdf_value(&self, origin: &Point, direction: &Vec3D, time: Real) -> Real {
+        let ray = Ray::new_timed(origin.clone(), direction.clone(), time);
+        if self
+            .hit(&ray, &Interval::new(0.001, math::INFINITY))
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center.at(time) - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+
+        if distance_squared <= radius_squared {
+            // the origin is inside the sphere, so every direction hits it
+            1.0 / (4.0 * math::PI)
+        } else {
+            let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+            let solid_angle = 2.0 * math::PI * (1.0 - cos_theta_max);
+            1.0 / solid_angle
+        }
+    }
+
+    /// Returns a random direction from `origin` towards the sphere, uniformly sampled from the
+    /// cone the sphere subtends. This is much less wasteful than sampling points on its surface.
+    pub(crate) fn random_direction(&self, origin: &Point, time: Real) -> Vec3D {
+        let direction = self.center.at(time) - origin;
+        let distance_squared = direction.length_squared();
+
+        if distance_squared <= self.radius * self.radius {
+            Vec3D::random_unit().0
+        } else {
+            let onb = Onb::new(&direction);
+            onb.transform(&Vec3D::random_to_sphere(self.radius, distance_squared))
+        }
+    }
+
     pub(super) fn compute_uv(&self, p: &Vec3D) -> (Real, Real) {
         // NOTE: `p` should have been a Point by definition, but I'll allow a Vec
         // this time to avoid having to cast
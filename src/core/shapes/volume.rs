@@ -1,6 +1,6 @@
 use crate::core::aabb::AABB;
 use crate::core::hittables::{FrontFace, HitPoint, HitRecord, Mat, Normal, ObjectRef, T, U, V};
-use crate::core::materials::Isotropic;
+use crate::core::materials::{Isotropic, MaterialKind};
 use crate::core::math::interval::Interval;
 use crate::core::math::vector::UnitVec3D;
 use crate::core::math::{Real, Vec3D};
@@ -27,7 +27,7 @@ impl ConstantMedium {
         Self::new(
             boundary,
             density,
-            Material::Isotropic(Isotropic::from_texture(texture)),
+            Material::new(MaterialKind::Isotropic(Isotropic::from_texture(texture))),
         )
     }
 
@@ -35,7 +35,7 @@ impl ConstantMedium {
         Self::new(
             boundary,
             density,
-            Material::Isotropic(Isotropic::from_albedo(albedo)),
+            Material::new(MaterialKind::Isotropic(Isotropic::from_albedo(albedo))),
         )
     }
 
@@ -80,6 +80,10 @@ impl ConstantMedium {
         }
     }
 
+    pub(crate) fn phase_function(&self) -> &Material {
+        &self.phase_function
+    }
+
     pub(crate) fn bounding_box(&self) -> &AABB {
         self.boundary.bounding_box()
     }
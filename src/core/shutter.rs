@@ -0,0 +1,79 @@
+use crate::core::math::Real;
+use serde::Deserialize;
+
+/// How the exposure is spread over the time the shutter is open
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ShutterCurve {
+    /// The whole image is exposed evenly while the shutter is open
+    #[default]
+    Box,
+
+    /// The exposure ramps up linearly until the middle of the interval, and back down, like a
+    /// shutter that takes a while to open and close. Softens the ends of the motion trails.
+    Triangle,
+
+    /// The scanlines are exposed one after the other, from the top of the image to the bottom,
+    /// so fast objects get skewed like with the electronic shutter of a phone.
+    Rolling,
+}
+
+/// When the camera takes its samples. The times are those of the scene, where moving objects
+/// go from their start to their end between 0 and 1. The motion isn't defined outside of that,
+/// so neither is the shutter.
+#[derive(Clone, Debug)]
+pub(crate) struct Shutter {
+    open: Real,
+    close: Real,
+    curve: ShutterCurve,
+
+    /// With the rolling curve, the fraction of the interval it takes to go from the first
+    /// scanline to the last. Each scanline is exposed for the rest of it.
+    rolling_duration: Real,
+}
+
+impl Shutter {
+    pub(crate) fn new(
+        open: Real,
+        close: Real,
+        curve: ShutterCurve,
+        rolling_duration: Real,
+    ) -> Self {
+        let clamped_open = open.clamp(0.0, 1.0);
+        let clamped_close = close.clamp(clamped_open, 1.0);
+        if (clamped_open, clamped_close) != (open, close) {
+            log::warn!(
+                "The shutter interval [{open}, {close}] is outside of [0, 1], where the motion is \
+                defined. Using [{clamped_open}, {clamped_close}] instead."
+            );
+        }
+
+        Self {
+            open: clamped_open,
+            close: clamped_close,
+            curve,
+            rolling_duration: rolling_duration.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Maps the uniform number `u` in [0, 1) to the time of a sample. `row` is the vertical
+    /// position of the sample in the image, from 0 at the top to 1 at the bottom.
+    pub(crate) fn sample_time(&self, u: Real, row: Real) -> Real {
+        let interval = self.close - self.open;
+        let fraction = match self.curve {
+            ShutterCurve::Box => u,
+            ShutterCurve::Triangle => {
+                if u < 0.5 {
+                    (2.0 * u).sqrt() / 2.0
+                } else {
+                    1.0 - (2.0 * (1.0 - u)).sqrt() / 2.0
+                }
+            }
+            ShutterCurve::Rolling => {
+                let start = self.rolling_duration * row.clamp(0.0, 1.0);
+                start + (1.0 - self.rolling_duration) * u
+            }
+        };
+        self.open + interval * fraction
+    }
+}
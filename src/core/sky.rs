@@ -0,0 +1,325 @@
+use crate::core::Color;
+use crate::core::environment::Environment;
+use crate::core::lights::Light;
+use crate::core::math::vector::UnitVec3D;
+use crate::core::math::{self, Real, Vec3D};
+
+/// Converts the luminances of the model, in kcd/m², to the units of the renderer, so that the sky
+/// at noon is about as bright as the usual backgrounds.
+const SKY_SCALE: Real = 0.03;
+
+/// The illuminance of the sun outside the atmosphere, in klx
+const SOLAR_ILLUMINANCE: Real = 128.0;
+
+/// The wavelengths of the red, green and blue channels, in micrometers
+const WAVELENGTHS: [Real; 3] = [0.68, 0.55, 0.44];
+
+/// The analytic daylight model of Preetham, Shirley and Smits ("A Practical Analytic Model for
+/// Daylight"): the sky above the horizon depends on the direction of the sun and the turbidity
+/// (the haziness) of the atmosphere, and the sun is a directional light reddened by the air it
+/// goes through. Below the horizon is a diffuse ground lit by both.
+#[derive(Debug)]
+pub(crate) struct Sky {
+    dome: Dome,
+
+    /// The light reflected by the ground, which is the same in every direction
+    ground: Color,
+
+    /// Absent once the sun is below the horizon
+    sun: Option<Sun>,
+
+    /// The sky tabulated for importance sampling
+    environment: Environment,
+}
+
+/// The sky above the horizon, without the sun
+#[derive(Debug)]
+struct Dome {
+    /// Towards the sun
+    sun_direction: UnitVec3D,
+
+    luminance: Perez,
+    chromaticity_x: Perez,
+    chromaticity_y: Perez,
+
+    /// From the luminances of the model to the colors of the renderer
+    scale: Real,
+}
+
+#[derive(Debug)]
+struct Sun {
+    light: Light,
+
+    /// Towards the sun
+    direction: UnitVec3D,
+
+    /// What the rays that hit the disk of the sun see. Zero for a sun without a size.
+    radiance: Color,
+
+    /// The cosine of half the angular diameter
+    cos_max_angle: Real,
+}
+
+impl Sky {
+    /// A sky lit by a sun in `sun_direction`. The turbidity goes from 2 for a very clear sky to
+    /// 10 for a hazy one. `intensity` scales all the light, and `sun_intensity` only the sun's.
+    /// The angular diameter of the sun is in degrees.
+    pub(crate) fn new(
+        sun_direction: Vec3D,
+        turbidity: Real,
+        intensity: Real,
+        sun_intensity: Real,
+        sun_angular_diameter: Real,
+        ground_albedo: Color,
+    ) -> Self {
+        let sun_direction = sun_direction.to_unit();
+        let turbidity = turbidity.clamp(1.7, 10.0);
+
+        // the model is only valid for a sun above the horizon
+        let cos_theta_sun = sun_direction.y.clamp(0.0, 1.0);
+        let theta_sun = cos_theta_sun.acos();
+
+        let chi = (4.0 / 9.0 - turbidity / 120.0) * (math::PI - 2.0 * theta_sun);
+        let zenith_luminance = ((4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity
+            + 2.4192)
+            .max(0.0);
+        let zenith_x = zenith_chromaticity(
+            [
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ],
+            turbidity,
+            theta_sun,
+        );
+        let zenith_y = zenith_chromaticity(
+            [
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ],
+            turbidity,
+            theta_sun,
+        );
+
+        let t = turbidity;
+        let luminance = Perez::new(
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            zenith_luminance,
+            theta_sun,
+        );
+        let chromaticity_x = Perez::new(
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            zenith_x,
+            theta_sun,
+        );
+        let chromaticity_y = Perez::new(
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+            zenith_y,
+            theta_sun,
+        );
+
+        let dome = Dome {
+            sun_direction: sun_direction.clone(),
+            luminance,
+            chromaticity_x,
+            chromaticity_y,
+            scale: SKY_SCALE * intensity,
+        };
+
+        let mut ground_irradiance = dome.irradiance();
+        let sun = (sun_direction.y > 0.0).then(|| {
+            let irradiance = sun_transmittance(turbidity, theta_sun)
+                * (SOLAR_ILLUMINANCE * SKY_SCALE * intensity * sun_intensity);
+            ground_irradiance = &ground_irradiance + &(&irradiance * sun_direction.y);
+
+            let cos_max_angle = math::degrees_to_radians(sun_angular_diameter / 2.0).cos();
+            let radiance = if cos_max_angle < 1.0 {
+                &irradiance / (2.0 * math::PI * (1.0 - cos_max_angle))
+            } else {
+                Color::black()
+            };
+            Sun {
+                light: Light::directional(
+                    -&sun_direction.0,
+                    irradiance,
+                    1.0,
+                    sun_angular_diameter,
+                ),
+                direction: sun_direction,
+                radiance,
+                cos_max_angle,
+            }
+        });
+
+        let ground = ground_albedo * ground_irradiance / math::PI;
+        let environment = Environment::from_radiance(256, 128, |direction| {
+            if direction.y < 0.0 {
+                ground.clone()
+            } else {
+                dome.value(direction)
+            }
+        });
+
+        Self {
+            dome,
+            ground,
+            sun,
+            environment,
+        }
+    }
+
+    /// The light coming from `direction`, without the sun
+    pub(crate) fn value(&self, direction: &Vec3D) -> Color {
+        if direction.y < 0.0 {
+            self.ground.clone()
+        } else {
+            self.dome.value(direction)
+        }
+    }
+
+    /// The light of the disk of the sun, if `direction` points at it
+    pub(crate) fn sun_value(&self, direction: &Vec3D) -> Color {
+        match &self.sun {
+            Some(sun) if direction.to_unit().dot(&sun.direction.0) >= sun.cos_max_angle => {
+                sun.radiance.clone()
+            }
+            _ => Color::black(),
+        }
+    }
+
+    pub(crate) fn sun(&self) -> Option<&Light> {
+        self.sun.as_ref().map(|sun| &sun.light)
+    }
+
+    pub(crate) fn environment(&self) -> &Environment {
+        &self.environment
+    }
+}
+
+impl Dome {
+    fn value(&self, direction: &Vec3D) -> Color {
+        let direction = direction.to_unit();
+
+        // the model goes wrong right at the horizon
+        let cos_theta = direction.y.max(0.01);
+        let gamma = direction
+            .dot(&self.sun_direction.0)
+            .clamp(-1.0, 1.0)
+            .acos();
+
+        let luminance = self.luminance.value(cos_theta, gamma);
+        let x = self.chromaticity_x.value(cos_theta, gamma);
+        let y = self.chromaticity_y.value(cos_theta, gamma);
+        xyy_to_linear_srgb(x, y, luminance) * self.scale
+    }
+
+    /// The light sent to a horizontal surface
+    fn irradiance(&self) -> Color {
+        let (thetas, phis) = (32, 64);
+        let d_theta = math::PI / 2.0 / thetas as Real;
+        let d_phi = 2.0 * math::PI / phis as Real;
+
+        let mut irradiance = Color::black();
+        for i in 0..thetas {
+            let theta = (i as Real + 0.5) * d_theta;
+            let (sin_theta, cos_theta) = theta.sin_cos();
+            for j in 0..phis {
+                let phi = (j as Real + 0.5) * d_phi;
+                let direction = Vec3D::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+                irradiance =
+                    irradiance + self.value(&direction) * (cos_theta * sin_theta * d_theta * d_phi);
+            }
+        }
+        irradiance
+    }
+}
+
+/// The distribution of a quantity over the sky, relative to its value at the zenith
+#[derive(Debug)]
+struct Perez {
+    coefficients: [Real; 5],
+    zenith: Real,
+
+    /// The distribution at the zenith, which the others get divided by
+    at_zenith: Real,
+}
+
+impl Perez {
+    fn new(coefficients: [Real; 5], zenith: Real, theta_sun: Real) -> Self {
+        let at_zenith = distribution(&coefficients, 1.0, theta_sun);
+        Self {
+            coefficients,
+            zenith,
+            at_zenith,
+        }
+    }
+
+    /// The value at an angle from the zenith whose cosine is `cos_theta`, `gamma` radians away
+    /// from the sun
+    fn value(&self, cos_theta: Real, gamma: Real) -> Real {
+        self.zenith * distribution(&self.coefficients, cos_theta, gamma) / self.at_zenith
+    }
+}
+
+fn distribution([a, b, c, d, e]: &[Real; 5], cos_theta: Real, gamma: Real) -> Real {
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+fn zenith_chromaticity(matrix: [[Real; 4]; 3], turbidity: Real, theta_sun: Real) -> Real {
+    let turbidities = [turbidity * turbidity, turbidity, 1.0];
+    let thetas = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+    matrix
+        .iter()
+        .zip(turbidities)
+        .map(|(row, t)| t * row.iter().zip(thetas).map(|(m, s)| m * s).sum::<Real>())
+        .sum()
+}
+
+/// The fraction of the sunlight that makes it through the air, at the wavelengths of the red,
+/// green and blue channels. Only the scattering by the molecules (Rayleigh) and by the aerosols
+/// (Ångström's formula) is taken into account.
+fn sun_transmittance(turbidity: Real, theta_sun: Real) -> Color {
+    // the relative optical mass, by Kasten's formula
+    let degrees = theta_sun.to_degrees();
+    let mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - degrees).powf(-1.253));
+
+    let beta = 0.04608 * turbidity - 0.04586;
+    let [r, g, b] = WAVELENGTHS.map(|lambda: Real| {
+        let rayleigh = 0.008735 * lambda.powf(-4.08);
+        let aerosol = beta * lambda.powf(-1.3);
+        (-(rayleigh + aerosol) * mass).exp()
+    });
+    Color::new(r, g, b)
+}
+
+fn xyy_to_linear_srgb(x: Real, y: Real, luminance: Real) -> Color {
+    if y <= 0.0 {
+        return Color::black();
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    Color::new(
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0),
+    )
+}
@@ -1,6 +1,6 @@
 use crate::core::math::interval::Interval;
 use crate::core::math::{Point, Real};
-use crate::core::{Color, math};
+use crate::core::Color;
 use image::{ImageReader, ImageResult, RgbImage};
 use perlin::Perlin;
 use std::sync::Arc;
@@ -117,24 +117,21 @@ impl ImageTexture {
 
             let color_scale = 1.0 / 255.0;
             Color::new(
-                Self::linear_color(color_scale * pixel[0] as f64),
-                Self::linear_color(color_scale * pixel[1] as f64),
-                Self::linear_color(color_scale * pixel[2] as f64),
+                Color::srgb_to_linear(color_scale * pixel[0] as f64),
+                Color::srgb_to_linear(color_scale * pixel[1] as f64),
+                Color::srgb_to_linear(color_scale * pixel[2] as f64),
             )
         }
     }
-
-    /// Gamma correction (gamma = 1)
-    fn linear_color(value: f64) -> f64 {
-        value.powf(math::GAMMA as f64)
-    }
 }
 
 #[derive(Clone, Debug)]
 pub(crate) struct SerializeableImage {
     width: u32,
     height: u32,
-    data: Vec<u8>,
+
+    /// Shared between the clones, so copying a material doesn't copy its image
+    data: Arc<[u8]>,
 }
 
 impl SerializeableImage {
@@ -165,14 +162,14 @@ impl From<RgbImage> for SerializeableImage {
         Self {
             width,
             height,
-            data: value.into_raw(),
+            data: value.into_raw().into(),
         }
     }
 }
 
 impl From<SerializeableImage> for RgbImage {
     fn from(value: SerializeableImage) -> Self {
-        if let Some(img) = RgbImage::from_raw(value.width, value.height, value.data) {
+        if let Some(img) = RgbImage::from_raw(value.width, value.height, value.data.to_vec()) {
             img
         } else {
             log::error!("Unable to create image from raw");
@@ -183,7 +180,7 @@ impl From<SerializeableImage> for RgbImage {
 
 #[derive(Clone, Debug)]
 pub(crate) struct NoiseTexture {
-    noise: Perlin,
+    noise: Arc<Perlin>,
     scale: f64,
     base_color: Color,
 }
@@ -191,7 +188,7 @@ pub(crate) struct NoiseTexture {
 impl NoiseTexture {
     pub(crate) fn new(scale: f64, base_color: Color) -> Self {
         Self {
-            noise: Perlin::new(),
+            noise: Arc::new(Perlin::new()),
             scale,
             base_color,
         }
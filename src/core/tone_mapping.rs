@@ -0,0 +1,149 @@
+use crate::core::Color;
+use crate::core::math::Real;
+use serde::Deserialize;
+
+/// Operators that compress the unbounded linear radiance into the displayable [0, 1] range.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ToneMapping {
+    /// Leaves the values as is. Anything above 1.0 gets clipped during quantization.
+    #[default]
+    Clamp,
+
+    /// `x / (1 + x)`
+    Reinhard,
+
+    /// Like [`Self::Reinhard`], but maps the configurable white point to 1.0 instead of infinity.
+    ExtendedReinhard,
+
+    /// John Hable's filmic curve from Uncharted 2
+    Hable,
+
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl ToneMapping {
+    fn map(&self, x: Real, white_point: Real) -> Real {
+        match self {
+            Self::Clamp => x,
+            Self::Reinhard => x / (1.0 + x),
+            Self::ExtendedReinhard => x * (1.0 + x / (white_point * white_point)) / (1.0 + x),
+            Self::Hable => {
+                const EXPOSURE_BIAS: Real = 2.0;
+                const LINEAR_WHITE: Real = 11.2;
+                Self::hable_partial(x * EXPOSURE_BIAS) / Self::hable_partial(LINEAR_WHITE)
+            }
+            Self::Aces => {
+                let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                (x * (a * x + b)) / (x * (c * x + d) + e)
+            }
+        }
+    }
+
+    fn hable_partial(x: Real) -> Real {
+        let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+        ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+    }
+}
+
+/// Applies the exposure and the tone mapping operator to the linear colors of a render.
+#[derive(Clone, Debug)]
+pub(crate) struct ToneMapper {
+    tone_mapping: ToneMapping,
+
+    /// Exposure in stops. Each stop doubles (or halves, if negative) the radiance.
+    exposure: Real,
+
+    /// The smallest radiance that gets mapped to pure white by [`ToneMapping::ExtendedReinhard`]
+    white_point: Real,
+}
+
+impl ToneMapper {
+    /// Anything lower would blow the white point up to infinity
+    const MIN_WHITE_POINT: Real = 1e-3;
+
+    pub(crate) fn new(tone_mapping: ToneMapping, exposure: Real, white_point: Real) -> Self {
+        // also catches NaN
+        let white_point = if white_point >= Self::MIN_WHITE_POINT {
+            white_point
+        } else {
+            log::warn!(
+                "The white point must be at least {}, but it is {white_point}. Using {} instead.",
+                Self::MIN_WHITE_POINT,
+                Self::MIN_WHITE_POINT
+            );
+            Self::MIN_WHITE_POINT
+        };
+
+        Self {
+            tone_mapping,
+            exposure,
+            white_point,
+        }
+    }
+
+    /// Scales the color by the exposure without compressing its range. This is what
+    /// floating-point outputs store.
+    pub(crate) fn expose(&self, color: &Color) -> Color {
+        color * Real::powf(2.0, self.exposure)
+    }
+
+    /// Exposes and tone maps the color. The result is still linear.
+    pub(crate) fn apply(&self, color: &Color) -> Color {
+        let exposed = self.expose(color);
+        let map = |component: Real| self.tone_mapping.map(component.max(0.0), self.white_point);
+        Color::new(
+            map(exposed.red_component()),
+            map(exposed.green_component()),
+            map(exposed.blue_component()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ToneMapper, ToneMapping};
+    use crate::core::Color;
+    use crate::core::math::Real;
+
+    const EPSILON: Real = 1e-9;
+
+    #[test]
+    fn test_black_stays_black() {
+        for tone_mapping in [
+            ToneMapping::Clamp,
+            ToneMapping::Reinhard,
+            ToneMapping::ExtendedReinhard,
+            ToneMapping::Hable,
+            ToneMapping::Aces,
+        ] {
+            assert!(tone_mapping.map(0.0, 4.0).abs() < EPSILON, "{tone_mapping:?}");
+        }
+    }
+
+    #[test]
+    fn test_white_endpoints() {
+        assert!((ToneMapping::ExtendedReinhard.map(4.0, 4.0) - 1.0).abs() < EPSILON);
+
+        // the linear white of Hable's curve, before the exposure bias
+        assert!((ToneMapping::Hable.map(5.6, 1.0) - 1.0).abs() < EPSILON);
+
+        let reinhard = ToneMapping::Reinhard.map(1e9, 1.0);
+        assert!(reinhard < 1.0 && reinhard > 1.0 - 1e-6);
+
+        let aces = ToneMapping::Aces.map(1e9, 1.0);
+        assert!((aces - 2.51 / 2.43).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_invalid_white_point() {
+        for white_point in [0.0, -1.0, Real::NAN] {
+            let tone_mapper = ToneMapper::new(ToneMapping::ExtendedReinhard, 0.0, white_point);
+            assert_eq!(tone_mapper.white_point, ToneMapper::MIN_WHITE_POINT);
+
+            let color = tone_mapper.apply(&Color::new(0.0, 0.5, 100.0));
+            assert!(color.x.is_finite() && color.y.is_finite() && color.z.is_finite());
+        }
+    }
+}
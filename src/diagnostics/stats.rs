@@ -1,5 +1,5 @@
-use crate::core::bvh::BVH;
-use crate::core::hittables::ObjectRef;
+use crate::core::bvh::{BVHBuilder, BVH};
+use crate::core::math::Real;
 use crate::core::Hittable;
 use crate::diagnostics::macros::define_flag;
 use std::fmt::Display;
@@ -14,44 +14,61 @@ pub(crate) struct BVHStats {
     max_depth: u32,
     depth_sum: u32,
     primitive_count: u32,
+
+    // the expected cost of tracing a ray through the tree, according to the Surface Area Heuristic
+    sah_cost: Real,
 }
 
 impl BVHStats {
     pub(crate) fn from_bvh(bvh: &BVH) -> Self {
         let mut this: Self = Default::default();
-        this.inspect_bvh(bvh, 0);
+        this.inspect_bvh(bvh, 0, bvh.bounding_box().surface_area());
         this
     }
 
-    fn inspect_bvh(&mut self, bvh: &BVH, depth: u32) {
-        self.internal_node_count += 1;
-        self.inspect_node(bvh.left(), depth + 1);
-        self.inspect_node(bvh.right(), depth + 1);
+    fn inspect_bvh(&mut self, bvh: &BVH, depth: u32, root_area: Real) {
+        if !bvh.nodes().is_empty() {
+            self.inspect_node(bvh, 0, depth, root_area);
+        }
     }
 
-    fn inspect_node(&mut self, node: ObjectRef, depth: u32) {
-        self.inspect_hittable(&*node, depth);
+    fn inspect_node(&mut self, bvh: &BVH, index: usize, depth: u32, root_area: Real) {
+        let node = &bvh.nodes()[index];
+        let area_ratio = node.bounding_box().surface_area() / root_area;
+
+        if node.is_leaf() {
+            self.leaf_count += 1;
+            if depth > self.max_depth {
+                self.max_depth = depth;
+            }
+            self.depth_sum += depth;
+
+            let objects = &bvh.objects()[node.primitives()];
+            self.sah_cost += BVHBuilder::INTERSECTION_COST * area_ratio * objects.len() as Real;
+            objects
+                .iter()
+                .for_each(|object| self.inspect_hittable(object, depth, root_area));
+        } else {
+            self.internal_node_count += 1;
+            self.sah_cost += BVHBuilder::TRAVERSAL_COST * area_ratio;
+            self.inspect_node(bvh, index + 1, depth + 1, root_area);
+            self.inspect_node(bvh, node.second_child(), depth + 1, root_area);
+        }
     }
 
-    fn inspect_hittable(&mut self, hittable: &Hittable, depth: u32) {
+    fn inspect_hittable(&mut self, hittable: &Hittable, depth: u32, root_area: Real) {
         match hittable {
             Hittable::Quadric(_)
             | Hittable::Planar(_)
             | Hittable::ConstantMedium(_)
-            | Hittable::Transform(_) => {
-                self.leaf_count += 1;
-                if depth > self.max_depth {
-                    self.max_depth = depth;
-                }
-                self.depth_sum += depth;
-                self.primitive_count += 1;
-            }
+            | Hittable::Transform(_)
+            | Hittable::Mesh(_) => self.primitive_count += 1,
             Hittable::List(list) => list
                 .objects()
                 .iter()
-                .for_each(|object| self.inspect_hittable(object, depth)),
+                .for_each(|object| self.inspect_hittable(object, depth, root_area)),
             Hittable::BVH(bvh) => {
-                self.inspect_bvh(bvh, depth);
+                self.inspect_bvh(bvh, depth, root_area);
             }
             Hittable::Plane(_) => (),
         }
@@ -117,14 +134,14 @@ impl BVHStats {
         );
 
         let avg_primitives_per_leaf = self.average_primitives_per_leaf();
-        let ideal_primitives_per_leaf = self.primitive_count as f32
-            / (self.primitive_count as f32 / BVH::PRIMITIVE_COUNT_PER_LEAF as f32).ceil();
         log_or_warn_ideal(
             "Average primitives per leaf",
-            ideal_primitives_per_leaf,
+            format!("at most {}", BVH::MAX_PRIMITIVES_PER_LEAF),
             avg_primitives_per_leaf,
-            avg_primitives_per_leaf / ideal_primitives_per_leaf > 0.9,
+            avg_primitives_per_leaf <= BVH::MAX_PRIMITIVES_PER_LEAF as f32,
         );
+
+        log::info!("SAH cost: {:.2}", self.sah_cost);
     }
 }
 
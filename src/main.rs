@@ -1,44 +1,49 @@
 use crate::bindings::schemas::SceneSchema;
 use crate::diagnostics::metrics;
-use config::{Config, File};
-use mlua::{AnyUserData, Lua, LuaSerdeExt};
-use std::{env, fs};
+use clap::Parser;
+use config::{Config, ConfigError, File};
+use mlua::{AnyUserData, Function, Lua, LuaSerdeExt, Table};
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
 
 pub(crate) mod bindings;
+mod cli;
 mod common;
 mod core;
 mod diagnostics;
 mod settings;
 
 fn main() -> mlua::Result<()> {
-    let args: Vec<String> = env::args().collect();
+    let args = cli::Args::parse();
 
-    if args.len() != 2 {
-        let error_message = "Usage: {} <script_name>";
-        eprintln!("{} {}", error_message, args[0]);
-        return Err(mlua::Error::external(error_message));
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = args.log_level() {
+        logger.filter_level(level);
     }
-
-    env_logger::init();
+    logger.init();
 
     log::info!("Loading configs...");
-    let settings = Config::builder()
-        .add_source(File::with_name("config"))
-        .build()
-        .map_err(mlua::Error::external)?
-        .try_deserialize::<settings::Config>()
-        .map_err(mlua::Error::external)?;
+    let settings = load_config(&args).map_err(mlua::Error::external)?;
     log::info!("Configs loaded.");
 
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(mlua::Error::external)?;
+    }
+
     diagnostics::setup(settings.app().diagnostics());
+    core::interrupt::install_handler().map_err(mlua::Error::external)?;
 
     let lua = Lua::new();
 
     path_setup(&lua)?;
-    engine_setup(&lua)?;
+    engine_setup(&lua, &args)?;
+    random_setup(&lua, settings.app().scene().camera().defaults().seed())?;
 
-    let script_name = args[1].clone();
-    let script_content = fs::read_to_string(script_name.clone())?;
+    let script_name = args.script.to_string_lossy().to_string();
+    let script_content = fs::read_to_string(&args.script)?;
 
     log::info!("Evaluating Lua script...");
     let scene_table: AnyUserData = lua.load(script_content).set_name(script_name).eval()?;
@@ -49,9 +54,41 @@ fn main() -> mlua::Result<()> {
     let result = scene.render(settings).map_err(mlua::Error::external);
 
     metrics::report();
+
+    // the partial image has been written, but scripts shouldn't mistake it for a finished one
+    if result.is_ok() && core::interrupt::is_interrupted() {
+        process::exit(core::interrupt::EXIT_CODE);
+    }
     result
 }
 
+/// Loads the config file, with the command line options overriding its values.
+fn load_config(args: &cli::Args) -> Result<settings::Config, ConfigError> {
+    let to_string = |path: &PathBuf| path.to_string_lossy().to_string();
+
+    Config::builder()
+        .add_source(File::with_name(&args.config.to_string_lossy()))
+        .set_override_option("app.scene.output_file", args.output.as_ref().map(to_string))?
+        .set_override_option(
+            "app.scene.sample_count_file",
+            args.sample_counts.as_ref().map(to_string),
+        )?
+        .set_override_option("app.scene.camera.overrides.image_width", args.width)?
+        .set_override_option("app.scene.camera.overrides.samples_per_pixel", args.spp)?
+        .set_override_option("app.scene.camera.overrides.max_depth", args.max_depth)?
+        .set_override_option(
+            "app.scene.progressive.checkpoint_file",
+            args.checkpoint.as_ref().map(to_string),
+        )?
+        .set_override_option(
+            "app.scene.progressive.resume_from",
+            args.resume.as_ref().map(to_string),
+        )?
+        .set_override_option("app.scene.progressive.time_limit", args.time_limit)?
+        .build()?
+        .try_deserialize()
+}
+
 /// Adds the current directory to the package paths
 fn path_setup(lua: &Lua) -> mlua::Result<()> {
     let cwd = env::current_dir()?;
@@ -63,8 +100,38 @@ fn path_setup(lua: &Lua) -> mlua::Result<()> {
     .exec()
 }
 
-fn engine_setup(lua: &Lua) -> mlua::Result<()> {
+/// Seeds the random numbers used while evaluating the script, both Lua's and the engine's,
+/// so the same script always describes the same scene.
+/// Seeds the random numbers of the engine and the ones of Lua together. A script that calls
+/// `math.randomseed` reseeds both, so what the engine builds from random numbers while the
+/// script runs, like the permutation tables of the Perlin noise, follows the script's seed.
+fn random_setup(lua: &Lua, seed: u64) -> mlua::Result<()> {
+    let math: Table = lua.globals().get("math")?;
+    let lua_random_seed: Function = math.get("randomseed")?;
+    lua_random_seed.call::<()>(seed as i64)?;
+    core::math::seed_rng(seed);
+
+    // Lua returns the seed it used, which is a random one when the script gives none
+    let random_seed = lua.create_function(move |_, seed: mlua::MultiValue| {
+        let (seed, increment): (i64, i64) = lua_random_seed.call(seed)?;
+        core::math::seed_rng(seed as u64);
+        Ok((seed, increment))
+    })?;
+    math.set("randomseed", random_seed)
+}
+
+/// Sets up the `engine` table and loads the helpers, which live next to the config file.
+fn engine_setup(lua: &Lua, args: &cli::Args) -> mlua::Result<()> {
     bindings::lua::set_engine(&lua)?;
-    let helpers = fs::read_to_string("scripts/helpers.lua")?;
-    lua.load(&helpers).exec()
+    bindings::lua::set_args(lua, &args.variables)?;
+
+    let helpers_path = args
+        .config
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("scripts/helpers.lua");
+    let helpers = fs::read_to_string(&helpers_path)?;
+    lua.load(&helpers)
+        .set_name(helpers_path.to_string_lossy())
+        .exec()
 }
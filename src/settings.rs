@@ -1,4 +1,10 @@
+use crate::core::aov::Pass;
+use crate::core::filter::Filter;
 use crate::core::math::Real;
+use crate::core::projection::Projection;
+use crate::core::sampler::Sampler;
+use crate::core::shutter::ShutterCurve;
+use crate::core::tone_mapping::ToneMapping;
 use serde::Deserialize;
 
 pub(crate) type Vec3D = [Real; 3];
@@ -40,7 +46,15 @@ impl AppConfig {
 #[derive(Deserialize, Clone)]
 pub(crate) struct SceneConfig {
     output_file: String,
+
+    /// Where to write an image of the number of samples taken by each pixel
+    #[serde(default)]
+    sample_count_file: Option<String>,
+
     camera: CameraConfig,
+
+    #[serde(default)]
+    progressive: ProgressiveConfig,
 }
 
 impl SceneConfig {
@@ -48,36 +62,195 @@ impl SceneConfig {
         &self.camera
     }
 
+    pub(crate) fn progressive(&self) -> &ProgressiveConfig {
+        &self.progressive
+    }
+
     pub(crate) fn output_file(&self) -> &str {
         &self.output_file
     }
+
+    pub(crate) fn sample_count_file(&self) -> Option<&str> {
+        self.sample_count_file.as_deref()
+    }
+}
+
+/// The render is split into passes over the whole image, each one adding a few samples to
+/// every pixel, so it can be checkpointed and stopped early.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct ProgressiveConfig {
+    samples_per_pass: u32,
+
+    /// Where the accumulated samples are saved. Defaults to `resume_from`. No checkpoints are
+    /// written without either of them.
+    checkpoint_file: Option<String>,
+
+    /// The minimum number of seconds between two checkpoints
+    checkpoint_interval: Real,
+
+    /// A checkpoint to continue the render from
+    resume_from: Option<String>,
+
+    /// The number of seconds after which no more passes get started
+    time_limit: Option<Real>,
+}
+
+impl Default for ProgressiveConfig {
+    fn default() -> Self {
+        Self {
+            samples_per_pass: 16,
+            checkpoint_file: None,
+            checkpoint_interval: 300.0,
+            resume_from: None,
+            time_limit: None,
+        }
+    }
+}
+
+impl ProgressiveConfig {
+    pub(crate) fn samples_per_pass(&self) -> u32 {
+        self.samples_per_pass
+    }
+
+    pub(crate) fn checkpoint_file(&self) -> Option<&str> {
+        self.checkpoint_file.as_deref()
+    }
+
+    pub(crate) fn checkpoint_interval(&self) -> Real {
+        self.checkpoint_interval
+    }
+
+    pub(crate) fn resume_from(&self) -> Option<&str> {
+        self.resume_from.as_deref()
+    }
+
+    pub(crate) fn time_limit(&self) -> Option<Real> {
+        self.time_limit
+    }
 }
 
 #[derive(Deserialize, Clone)]
 pub(crate) struct CameraConfig {
     defaults: CameraDefaults,
+
+    #[serde(default)]
+    overrides: CameraOverrides,
 }
 
 impl CameraConfig {
     pub(crate) fn defaults(&self) -> &CameraDefaults {
         &self.defaults
     }
+
+    pub(crate) fn overrides(&self) -> &CameraOverrides {
+        &self.overrides
+    }
+}
+
+/// Unlike [`CameraDefaults`], these win over the values set by the scene.
+/// The command line fills them in for quick previews.
+#[derive(Deserialize, Clone, Default)]
+pub(crate) struct CameraOverrides {
+    image_width: Option<u32>,
+    samples_per_pixel: Option<u32>,
+    max_depth: Option<u32>,
+}
+
+impl CameraOverrides {
+    pub(crate) fn image_width(&self) -> Option<u32> {
+        self.image_width
+    }
+
+    pub(crate) fn samples_per_pixel(&self) -> Option<u32> {
+        self.samples_per_pixel
+    }
+
+    pub(crate) fn max_depth(&self) -> Option<u32> {
+        self.max_depth
+    }
 }
 
 #[derive(Deserialize, Clone)]
 pub(crate) struct CameraDefaults {
     samples_per_pixel: u32,
     antialiasing: bool,
+
+    #[serde(default)]
+    filter: Filter,
+
+    /// Defaults to the radius the filter is designed for
+    #[serde(default)]
+    filter_radius: Option<Real>,
+
     max_depth: u32,
+
+    #[serde(default)]
+    projection: Projection,
+
     field_of_view: Real,
+
+    /// Defaults to the height the perspective projection shows at the focus distance
+    #[serde(default)]
+    orthographic_height: Option<Real>,
+
     look_from: Point,
     look_at: Point,
     vup: Vec3D,
     defocus_angle: Real,
     focus_distance: Real,
+
+    /// The time the shutter opens at. Moving objects are at their start at 0.
+    #[serde(default)]
+    shutter_open: Real,
+
+    /// The time the shutter closes at. Moving objects are at their end at 1.
+    #[serde(default = "CameraDefaults::default_shutter_close")]
+    shutter_close: Real,
+
+    #[serde(default)]
+    shutter_curve: ShutterCurve,
+
+    /// The fraction of the shutter interval the rolling shutter takes to reach the last
+    /// scanline
+    #[serde(default = "CameraDefaults::default_rolling_shutter_duration")]
+    rolling_shutter_duration: Real,
+
     background: Color,
     tile_width: u32,
     tile_height: u32,
+
+    #[serde(default)]
+    tone_mapping: ToneMapping,
+
+    /// In stops
+    #[serde(default)]
+    exposure: Real,
+
+    /// The radiance that [`ToneMapping::ExtendedReinhard`] maps to white
+    #[serde(default = "CameraDefaults::default_white_point")]
+    white_point: Real,
+
+    #[serde(default)]
+    seed: u64,
+
+    #[serde(default)]
+    sampler: Sampler,
+
+    /// The samples every pixel gets before adaptive sampling can consider it converged
+    #[serde(default = "CameraDefaults::default_min_samples_per_pixel")]
+    min_samples_per_pixel: u32,
+
+    /// The relative error under which a pixel stops getting samples. 0 disables adaptive
+    /// sampling.
+    #[serde(default)]
+    adaptive_threshold: Real,
+
+    #[serde(default)]
+    passes: Vec<Pass>,
+
+    #[serde(default)]
+    denoise: bool,
 }
 
 impl CameraDefaults {
@@ -89,14 +262,30 @@ impl CameraDefaults {
         self.antialiasing
     }
 
+    pub(crate) fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    pub(crate) fn filter_radius(&self) -> Option<Real> {
+        self.filter_radius
+    }
+
     pub(crate) fn max_depth(&self) -> u32 {
         self.max_depth
     }
 
+    pub(crate) fn projection(&self) -> Projection {
+        self.projection
+    }
+
     pub(crate) fn field_of_view(&self) -> Real {
         self.field_of_view
     }
 
+    pub(crate) fn orthographic_height(&self) -> Option<Real> {
+        self.orthographic_height
+    }
+
     pub(crate) fn look_from(&self) -> Point {
         self.look_from
     }
@@ -117,6 +306,22 @@ impl CameraDefaults {
         self.focus_distance
     }
 
+    pub(crate) fn shutter_open(&self) -> Real {
+        self.shutter_open
+    }
+
+    pub(crate) fn shutter_close(&self) -> Real {
+        self.shutter_close
+    }
+
+    pub(crate) fn shutter_curve(&self) -> ShutterCurve {
+        self.shutter_curve
+    }
+
+    pub(crate) fn rolling_shutter_duration(&self) -> Real {
+        self.rolling_shutter_duration
+    }
+
     pub(crate) fn background(&self) -> Color {
         self.background
     }
@@ -128,6 +333,58 @@ impl CameraDefaults {
     pub(crate) fn tile_height(&self) -> u32 {
         self.tile_height
     }
+
+    pub(crate) fn tone_mapping(&self) -> ToneMapping {
+        self.tone_mapping
+    }
+
+    pub(crate) fn exposure(&self) -> Real {
+        self.exposure
+    }
+
+    pub(crate) fn white_point(&self) -> Real {
+        self.white_point
+    }
+
+    pub(crate) fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub(crate) fn sampler(&self) -> Sampler {
+        self.sampler
+    }
+
+    pub(crate) fn min_samples_per_pixel(&self) -> u32 {
+        self.min_samples_per_pixel
+    }
+
+    pub(crate) fn adaptive_threshold(&self) -> Real {
+        self.adaptive_threshold
+    }
+
+    pub(crate) fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    pub(crate) fn denoise(&self) -> bool {
+        self.denoise
+    }
+
+    fn default_min_samples_per_pixel() -> u32 {
+        16
+    }
+
+    fn default_shutter_close() -> Real {
+        1.0
+    }
+
+    fn default_rolling_shutter_duration() -> Real {
+        0.1
+    }
+
+    fn default_white_point() -> Real {
+        4.0
+    }
 }
 
 #[derive(Deserialize, Clone)]